@@ -93,7 +93,12 @@ impl Aabb {
         )
     }
 
-    pub fn hit(&self, ray: Ray, mut ray_t: Interval) -> bool {
+    pub fn hit(&self, ray: Ray, ray_t: Interval) -> bool {
+        self.clip(ray, ray_t).is_some()
+    }
+
+    // The part of ray_t inside the box
+    pub fn clip(&self, ray: Ray, mut ray_t: Interval) -> Option<Interval> {
         for axis in 0..=2 {
             let axis_interval = self[axis];
             let adinv = 1.0 / ray.direction[axis];
@@ -118,10 +123,20 @@ impl Aabb {
             }
 
             if ray_t.max <= ray_t.min {
-                return false;
+                return None;
             }
         }
-        true
+        Some(ray_t)
+    }
+
+    // False for boxes reaching infinity along any axis, like those of infinite planes
+    pub const fn is_bounded(&self) -> bool {
+        self.x.min.is_finite()
+            && self.x.max.is_finite()
+            && self.y.min.is_finite()
+            && self.y.max.is_finite()
+            && self.z.min.is_finite()
+            && self.z.max.is_finite()
     }
 
     pub const fn longest_axis(&self) -> usize {
@@ -0,0 +1,357 @@
+use std::{fmt::Debug, ops::RangeInclusive, path::PathBuf, sync::Arc};
+
+use anyhow::Context;
+use glam::{Mat4, Quat, Vec2, Vec3};
+
+use crate::{
+    bvh::BvhNode,
+    camera::Camera,
+    hit::Hittable,
+    hittable_list::HittableList,
+    transform::{AnimatedTransform, TransformKeyframe},
+};
+
+// Values that can be keyframed
+pub trait Animatable: Copy + Debug {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Animatable for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + t * (other - self)
+    }
+}
+
+impl Animatable for Vec2 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Vec2::lerp(self, other, t)
+    }
+}
+
+impl Animatable for Vec3 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Vec3::lerp(self, other, t)
+    }
+}
+
+// Along the shortest arc
+impl Animatable for Quat {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+}
+
+// How a keyframe moves on to the next one
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum Interpolation {
+    // holds the value until the next keyframe
+    Constant,
+    #[default]
+    Linear,
+    // cubic Bezier timing curve from (0, 0) to (1, 1) through the two handles, as in CSS
+    Bezier(Vec2, Vec2),
+}
+
+impl Interpolation {
+    pub const EASE: Self = Self::Bezier(Vec2::new(0.25, 0.1), Vec2::new(0.25, 1.0));
+    pub const EASE_IN: Self = Self::Bezier(Vec2::new(0.42, 0.0), Vec2::new(1.0, 1.0));
+    pub const EASE_OUT: Self = Self::Bezier(Vec2::new(0.0, 0.0), Vec2::new(0.58, 1.0));
+    pub const EASE_IN_OUT: Self = Self::Bezier(Vec2::new(0.42, 0.0), Vec2::new(0.58, 1.0));
+
+    // Maps the fraction of time between two keyframes to the fraction of the way between their
+    // values
+    pub fn ease(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Constant => 0.0,
+            Self::Linear => t,
+            Self::Bezier(p1, p2) => {
+                let bezier = |a: f32, b: f32, s: f32| {
+                    let r = 1.0 - s;
+                    3.0 * r * r * s * a + 3.0 * r * s * s * b + s * s * s
+                };
+                // the handles keep x monotonic in [0, 1], so bisection finds s for x = t
+                let (x1, x2) = (p1.x.clamp(0.0, 1.0), p2.x.clamp(0.0, 1.0));
+                let mut low = 0.0;
+                let mut high = 1.0;
+                for _ in 0..24 {
+                    let mid = 0.5 * (low + high);
+                    if bezier(x1, x2, mid) < t {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                bezier(p1.y, p2.y, 0.5 * (low + high))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Keyframe<T> {
+    pub time: f32, // seconds
+    pub value: T,
+    pub interpolation: Interpolation, // towards the next keyframe
+}
+
+// Keyframed value, held at the first and last keyframes outside of them
+#[derive(Clone, PartialEq, Debug)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>, // sorted by time
+}
+
+impl<T: Animatable> Track<T> {
+    // A single keyframe at time 0, which eases linearly into any keys added after it
+    pub fn constant(value: T) -> Self {
+        Self {
+            keyframes: vec![Keyframe {
+                time: 0.0,
+                value,
+                interpolation: Interpolation::default(),
+            }],
+        }
+    }
+
+    pub fn with_key(mut self, time: f32, value: T, interpolation: Interpolation) -> Self {
+        self.add_key(time, value, interpolation);
+        self
+    }
+
+    // Replaces a keyframe at the same time
+    pub fn add_key(&mut self, time: f32, value: T, interpolation: Interpolation) {
+        let keyframe = Keyframe {
+            time,
+            value,
+            interpolation,
+        };
+        let index = self.keyframes.partition_point(|key| key.time < time);
+        match self.keyframes.get_mut(index) {
+            Some(key) if key.time == time => *key = keyframe,
+            _ => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    pub fn value_at(&self, time: f32) -> T {
+        let next = self.keyframes.partition_point(|key| key.time <= time);
+        if next == 0 {
+            return self.keyframes[0].value;
+        }
+        let previous = &self.keyframes[next - 1];
+        let Some(next) = self.keyframes.get(next) else {
+            return previous.value;
+        };
+        let t = (time - previous.time) / (next.time - previous.time);
+        previous
+            .value
+            .lerp(next.value, previous.interpolation.ease(t))
+    }
+}
+
+// Keyframed translation, rotation and scale of an object
+#[derive(Clone, PartialEq, Debug)]
+pub struct TransformTrack {
+    pub translation: Track<Vec3>,
+    pub rotation: Track<Quat>,
+    pub scale: Track<Vec3>,
+}
+
+impl TransformTrack {
+    // Steps per shutter interval when handing the motion to an AnimatedTransform
+    const SHUTTER_STEPS: usize = 4;
+
+    pub fn new(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Self {
+            translation: Track::constant(translation),
+            rotation: Track::constant(rotation),
+            scale: Track::constant(scale),
+        }
+    }
+
+    pub fn keyframe_at(&self, time: f32) -> TransformKeyframe {
+        TransformKeyframe::new(
+            time,
+            self.translation.value_at(time),
+            self.rotation.value_at(time),
+            self.scale.value_at(time),
+        )
+    }
+
+    pub fn matrix_at(&self, time: f32) -> Mat4 {
+        self.keyframe_at(time).matrix()
+    }
+
+    // object as it moves while the shutter is open, for motion blur
+    // Camera::with_shutter has to be given the same interval
+    pub fn animated_transform(
+        &self,
+        object: Arc<dyn Hittable>,
+        (shutter_open, shutter_close): (f32, f32),
+    ) -> AnimatedTransform {
+        let keyframes = (0..=Self::SHUTTER_STEPS)
+            .map(|step| {
+                let t = step as f32 / Self::SHUTTER_STEPS as f32;
+                self.keyframe_at(shutter_open + t * (shutter_close - shutter_open))
+            })
+            .collect();
+        AnimatedTransform::new(object, keyframes)
+    }
+}
+
+// Frames and their times, in seconds
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Timeline {
+    pub frame_rate: f32,
+    pub first_frame: u32,
+    pub last_frame: u32,
+    pub shutter: f32, // open fraction of a frame, 0.5 for a 180 degree shutter
+}
+
+impl Timeline {
+    pub const fn new(frame_rate: f32, first_frame: u32, last_frame: u32) -> Self {
+        Self {
+            frame_rate,
+            first_frame,
+            last_frame,
+            shutter: 0.5,
+        }
+    }
+
+    pub fn frames(&self) -> RangeInclusive<u32> {
+        self.first_frame..=self.last_frame
+    }
+
+    pub fn frame_time(&self, frame: u32) -> f32 {
+        frame as f32 / self.frame_rate
+    }
+
+    // The shutter opens at the frame time
+    pub fn shutter_interval(&self, frame: u32) -> (f32, f32) {
+        let open = self.frame_time(frame);
+        (open, open + self.shutter / self.frame_rate)
+    }
+}
+
+// What render_sequence renders for one frame
+#[derive(Debug)]
+pub struct Frame {
+    pub world: HittableList,
+    pub lights: HittableList,
+    pub camera: Camera,
+}
+
+// Command line options of a sequence render
+// --frames 10..20 (inclusive) or --frames 12, --skip-existing, --output <directory>
+#[derive(Clone, PartialEq, Debug)]
+pub struct SequenceOptions {
+    pub output_directory: PathBuf,
+    pub frames: Option<RangeInclusive<u32>>, // the whole timeline if none
+    pub skip_existing: bool,
+}
+
+impl Default for SequenceOptions {
+    fn default() -> Self {
+        Self {
+            output_directory: PathBuf::from("frames"),
+            frames: None,
+            skip_existing: false,
+        }
+    }
+}
+
+impl SequenceOptions {
+    // None without --sequence, for a single still
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Option<Self>> {
+        let mut options = Self::default();
+        let mut is_sequence = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sequence" => is_sequence = true,
+                "--skip-existing" => options.skip_existing = true,
+                "--output" => {
+                    options.output_directory =
+                        args.next().context("--output needs a directory")?.into();
+                }
+                "--frames" => {
+                    let range = args.next().context("--frames needs a frame or a range")?;
+                    options.frames = Some(parse_frame_range(&range)?);
+                    is_sequence = true;
+                }
+                _ => anyhow::bail!("Unknown argument \"{arg}\"."),
+            }
+        }
+
+        Ok(is_sequence.then_some(options))
+    }
+
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        self.output_directory.join(format!("frame_{frame:04}.png"))
+    }
+}
+
+fn parse_frame_range(range: &str) -> anyhow::Result<RangeInclusive<u32>> {
+    let parse = |frame: &str| {
+        frame
+            .trim()
+            .parse::<u32>()
+            .with_context(|| format!("Invalid frame \"{frame}\"."))
+    };
+    let range = match range.split_once("..") {
+        Some((first, last)) => parse(first)?..=parse(last.trim_start_matches('='))?,
+        None => {
+            let frame = parse(range)?;
+            frame..=frame
+        }
+    };
+    anyhow::ensure!(!range.is_empty(), "Empty frame range.");
+    Ok(range)
+}
+
+// Renders the frames of timeline picked by options to numbered images, building each one with
+// build_frame at its frame number
+// Camera parameters and material values are animated by sampling their Tracks at the frame time
+// in build_frame, object motion by TransformTrack::animated_transform
+pub fn render_sequence(
+    timeline: &Timeline,
+    options: &SequenceOptions,
+    mut build_frame: impl FnMut(u32) -> anyhow::Result<Frame>,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&options.output_directory).with_context(|| {
+        format!(
+            "Failed to create output directory {:?}.",
+            options.output_directory
+        )
+    })?;
+
+    let frames = options.frames.clone().unwrap_or(timeline.frames());
+    let frame_count = frames.clone().count();
+    for (index, frame) in frames.enumerate() {
+        let path = options.frame_path(frame);
+        if options.skip_existing && path.exists() {
+            eprintln!("Skipping frame {frame}, {path:?} exists.");
+            continue;
+        }
+        eprintln!("Frame {frame} ({}/{frame_count})", index + 1);
+
+        let Frame {
+            world,
+            lights,
+            camera,
+        } = build_frame(frame)?;
+        let bvh = BvhNode::from_hittable_list(world, -1);
+
+        let mut imgbuf = image::RgbImage::new(camera.image_width(), camera.image_height());
+        camera.render_threaded(&bvh, Arc::new(lights), &mut imgbuf);
+        imgbuf
+            .save(&path)
+            .with_context(|| format!("Failed to save frame {path:?}."))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::{f32::consts::TAU, fmt::Debug};
+
+use anyhow::Context;
+use glam::Vec2;
+use rand::{Rng, RngCore};
+
+use crate::util::random_in_unit_disk;
+
+// Shape of the lens opening, scaled by the lens radius of the projection
+pub trait Aperture: Send + Sync + Debug {
+    // point inside the unit disk, or the [-1,1] square for images, with +y up
+    fn sample(&self, rng: &mut dyn RngCore) -> Vec2;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CircularAperture;
+
+impl Aperture for CircularAperture {
+    fn sample(&self, rng: &mut dyn RngCore) -> Vec2 {
+        random_in_unit_disk(rng)
+    }
+}
+
+// Regular polygon of straight diaphragm blades inscribed in the unit circle
+#[derive(Clone, Copy, Debug)]
+pub struct PolygonalAperture {
+    pub blades: u32,
+    pub rotation: f32, // degrees, counterclockwise from a vertex on +x
+}
+
+impl PolygonalAperture {
+    pub fn new(blades: u32, rotation: f32) -> Self {
+        assert!(blades >= 3, "An aperture needs at least 3 blades.");
+        Self { blades, rotation }
+    }
+
+    fn vertex(&self, index: u32) -> Vec2 {
+        Vec2::from_angle(self.rotation.to_radians() + TAU * index as f32 / self.blades as f32)
+    }
+}
+
+impl Aperture for PolygonalAperture {
+    fn sample(&self, rng: &mut dyn RngCore) -> Vec2 {
+        // the triangles from the center to each edge all have the same area
+        let edge = rng.random_range(0..self.blades);
+        let (a, b) = (self.vertex(edge), self.vertex(edge + 1));
+
+        let mut u = rng.random::<f32>();
+        let mut v = rng.random::<f32>();
+        if u + v > 1.0 {
+            (u, v) = (1.0 - u, 1.0 - v);
+        }
+        u * a + v * b
+    }
+}
+
+// Grayscale transmission image stretched over the [-1,1] square, sampled in proportion to the
+// gray values
+#[derive(Clone, Debug)]
+pub struct ImageAperture {
+    width: u32,
+    height: u32,
+    row_cdf: Vec<f32>,    // height entries, marginal over rows
+    column_cdf: Vec<f32>, // width x height entries, conditional within each row
+}
+
+impl ImageAperture {
+    pub fn new(image: &image::GrayImage) -> anyhow::Result<Self> {
+        let (width, height) = image.dimensions();
+
+        let mut column_cdf = Vec::with_capacity((width * height) as usize);
+        let mut row_cdf = Vec::with_capacity(height as usize);
+        let mut total = 0.0;
+        for row in image.rows() {
+            let row_start = column_cdf.len();
+            let mut row_total = 0.0;
+            for pixel in row {
+                row_total += pixel.0[0] as f32;
+                column_cdf.push(row_total);
+            }
+            if row_total > 0.0 {
+                column_cdf[row_start..]
+                    .iter_mut()
+                    .for_each(|value| *value /= row_total);
+            }
+            total += row_total;
+            row_cdf.push(total);
+        }
+
+        if total <= 0.0 {
+            anyhow::bail!("Aperture image is completely black.");
+        }
+        row_cdf.iter_mut().for_each(|value| *value /= total);
+
+        Ok(Self {
+            width,
+            height,
+            row_cdf,
+            column_cdf,
+        })
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let image = image::ImageReader::open(path)?
+            .with_guessed_format()?
+            .decode()
+            .with_context(|| format!("Failed to decode aperture image {path:?}."))?;
+        Self::new(&image.into_luma8())
+    }
+}
+
+impl Aperture for ImageAperture {
+    fn sample(&self, rng: &mut dyn RngCore) -> Vec2 {
+        let find =
+            |cdf: &[f32], value: f32| cdf.partition_point(|&c| c <= value).min(cdf.len() - 1);
+
+        let y = find(&self.row_cdf, rng.random::<f32>());
+        let row_start = y * self.width as usize;
+        let row = &self.column_cdf[row_start..row_start + self.width as usize];
+        let x = find(row, rng.random::<f32>());
+
+        let pixel = Vec2::new(
+            x as f32 + rng.random::<f32>(),
+            y as f32 + rng.random::<f32>(),
+        );
+        let size = Vec2::new(self.width as f32, self.height as f32);
+        Vec2::new(2.0 * pixel.x / size.x - 1.0, 1.0 - 2.0 * pixel.y / size.y)
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3};
+use rand::{Rng, RngCore};
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable, uniform_area_pdf_value},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+};
+
+// Solid box between two corners, hit with the slab test instead of six quads
+#[derive(Clone, Debug)]
+pub struct AxisAlignedBox {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl AxisAlignedBox {
+    pub fn new(a: Vec3, b: Vec3, material: Arc<dyn Material>) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+            material,
+        }
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // The face with its normal along axis, on the max side if positive
+    // u and v run over the two following axes, [0,1] across the face
+    fn hit_record(&self, ray: Ray, t: f32, axis: usize, positive: bool) -> HitRecord {
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let size = self.size();
+
+        let mut point = ray.at(t);
+        point[axis] = if positive {
+            self.max[axis]
+        } else {
+            self.min[axis]
+        };
+        // a flat box has zero extent along an axis, where u or v stays 0
+        let relative = Vec3::select(
+            size.cmpgt(Vec3::ZERO),
+            (point - self.min) / size,
+            Vec3::ZERO,
+        );
+        let uv = Vec2::new(relative[u_axis], relative[v_axis]);
+
+        let mut normal = Vec3::ZERO;
+        normal[axis] = if positive { 1.0 } else { -1.0 };
+
+        let mut hit_record = HitRecord::new(ray, point, normal, self.material.clone(), t, uv);
+        hit_record.dpdu[u_axis] = size[u_axis];
+        hit_record.dpdv[v_axis] = size[v_axis];
+        hit_record
+    }
+}
+
+impl Hittable for AxisAlignedBox {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        // entry and exit distances, with the axis and side of the face crossed there
+        let mut entry = (f32::NEG_INFINITY, 0, false);
+        let mut exit = (f32::INFINITY, 0, false);
+
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+
+            // entering through the min face when travelling towards +axis
+            let (near, far) = if inverse_direction < 0.0 {
+                ((t1, axis, true), (t0, axis, false))
+            } else {
+                ((t0, axis, false), (t1, axis, true))
+            };
+
+            // NaN for rays in the plane of a face, which then never limit the interval
+            if near.0 > entry.0 {
+                entry = near;
+            }
+            if far.0 < exit.0 {
+                exit = far;
+            }
+        }
+
+        if entry.0 > exit.0 {
+            return None;
+        }
+
+        [entry, exit]
+            .into_iter()
+            .filter(|&(t, _, _)| ray_t.surrounds(t))
+            .map(|(t, axis, positive)| self.hit_record(ray, t, axis, positive))
+            .find(|hit_record| self.material.alpha_test(hit_record, rng))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_corners(self.min, self.max)
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut dyn RngCore) -> f32 {
+        uniform_area_pdf_value(self, self.area(), origin, direction, rng)
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let size = self.size();
+        let face_areas = [size.y * size.z, size.z * size.x, size.x * size.y];
+
+        // a face pair by area, then one of the two
+        let mut choice = rng.random::<f32>() * (face_areas[0] + face_areas[1] + face_areas[2]);
+        let mut axis = 0;
+        while axis < 2 && choice >= face_areas[axis] {
+            choice -= face_areas[axis];
+            axis += 1;
+        }
+
+        let mut point = self.min + size * Vec3::new(rng.random(), rng.random(), rng.random());
+        point[axis] = if rng.random::<bool>() {
+            self.max[axis]
+        } else {
+            self.min[axis]
+        };
+        point - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::LambertianMaterial, texture::SolidColor};
+
+    #[test]
+    fn flat_box_has_finite_uv() {
+        let material = Arc::new(LambertianMaterial::new(Arc::new(SolidColor::splat(0.5))));
+        let flat =
+            AxisAlignedBox::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(2.0, 4.0, 1.0), material);
+
+        let hit_record = flat
+            .hit(
+                Ray::new(Vec3::new(1.0, 1.0, 3.0), Vec3::NEG_Z),
+                Interval::new(0.001, f32::INFINITY),
+                &mut rand::rng(),
+            )
+            .unwrap();
+        assert_eq!(hit_record.t, 2.0);
+        assert_eq!(hit_record.normal, Vec3::Z);
+        assert_eq!(hit_record.uv, Vec2::new(0.5, 0.25));
+    }
+}
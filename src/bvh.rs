@@ -67,9 +67,32 @@ impl BvhNode {
         Self { left, right, bbox }
     }
 
-    pub fn from_hittable_list(mut list: HittableList, max_depth: i32) -> Self {
-        let len = list.objects.len();
-        Self::new(&mut list.objects, 0, len, max_depth)
+    // Unbounded objects like infinite planes would stretch every node above them, so they are
+    // kept in a list beside the tree instead
+    pub fn from_hittable_list(list: HittableList, max_depth: i32) -> Self {
+        let (mut bounded, mut unbounded): (Vec<_>, Vec<_>) = list
+            .objects
+            .into_iter()
+            .partition(|object| object.bounding_box().is_bounded());
+
+        let len = bounded.len();
+        if unbounded.is_empty() {
+            return Self::new(&mut bounded, 0, len, max_depth);
+        }
+
+        let left: Arc<dyn Hittable> = if bounded.is_empty() {
+            Arc::new(EmptyHittable)
+        } else {
+            Arc::new(Self::new(&mut bounded, 0, len, max_depth))
+        };
+        let right = HittableList::from_vec(&mut unbounded);
+        let bbox = Aabb::merged(left.bounding_box(), right.bounding_box());
+
+        Self {
+            left,
+            right: Arc::new(right),
+            bbox,
+        }
     }
 
     #[inline]
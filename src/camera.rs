@@ -4,20 +4,21 @@ use std::sync::{
 };
 
 use either::Either;
-use glam::Vec3;
+use glam::{Vec2, Vec3};
 use rand::Rng;
 use rayon::iter::ParallelIterator;
 
 use crate::{
+    aperture::{Aperture, CircularAperture},
     color::vec3_to_rgb8,
     hit::Hittable,
     interval::Interval,
     pdf::{HittablePdf, MixturePdf, Pdf},
-    ray::Ray,
-    util::random_in_unit_disk,
+    projection::{PerspectiveProjection, Projection},
+    ray::{Ray, RayDifferential},
 };
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Camera {
     image_width: u32,
     image_height: u32,
@@ -27,12 +28,16 @@ pub struct Camera {
     recip_sqrt_spp: f32, // 1 / sqrt(samples per pixel)
     max_depth: i32,
     background_color: Vec3,
-    pixel00_loc: Vec3,
-    pixel_delta_u: Vec3,
-    pixel_delta_v: Vec3,
-    defocus_angle: f32,
-    defocus_disk_u: Vec3,
-    defocus_disk_v: Vec3,
+    u: Vec3, // camera right
+    v: Vec3, // camera up
+    w: Vec3, // into camera from lookat
+    projection: Arc<dyn Projection>,
+    aperture: Arc<dyn Aperture>,
+    cats_eye: f32, // lens offset of the vignetting circle at the image corners
+    anamorphic_squeeze: f32, // horizontal squeeze of the aperture
+    shutter_open: f32, // ray times are spread over [shutter_open, shutter_close)
+    shutter_close: f32,
+    exposure: f32, // scales the rendered radiance
 }
 
 impl Camera {
@@ -67,13 +72,22 @@ impl Camera {
                         let mut pixel_color = Vec3::ZERO;
                         for s_y in 0..self.sqrt_spp {
                             for s_x in 0..self.sqrt_spp {
-                                let ray = self.get_ray(x, y, s_x, s_y, rng);
-                                pixel_color +=
-                                    self.ray_color(ray, self.max_depth, world, lights.clone(), rng);
+                                // film points the projection does not cover stay black
+                                if let Some((ray, weight)) = self.get_ray(x, y, s_x, s_y, rng) {
+                                    pixel_color += weight
+                                        * self.ray_color(
+                                            ray,
+                                            self.max_depth,
+                                            world,
+                                            lights.clone(),
+                                            rng,
+                                        );
+                                }
                             }
                         }
 
-                        *pixel = vec3_to_rgb8(self.pixel_samples_scale * pixel_color);
+                        *pixel =
+                            vec3_to_rgb8(self.pixel_samples_scale * self.exposure * pixel_color);
                         pixels_done.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                     });
                 is_done.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -105,12 +119,14 @@ impl Camera {
             let mut pixel_color = Vec3::ZERO;
             for s_y in 0..self.sqrt_spp {
                 for s_x in 0..self.sqrt_spp {
-                    let ray = self.get_ray(x, y, s_x, s_y, rng);
-                    pixel_color += self.ray_color(ray, self.max_depth, world, lights.clone(), rng);
+                    if let Some((ray, weight)) = self.get_ray(x, y, s_x, s_y, rng) {
+                        pixel_color += weight
+                            * self.ray_color(ray, self.max_depth, world, lights.clone(), rng);
+                    }
                 }
             }
 
-            *pixel = vec3_to_rgb8(self.pixel_samples_scale * pixel_color);
+            *pixel = vec3_to_rgb8(self.pixel_samples_scale * self.exposure * pixel_color);
             pixel_num += 1;
         }
         eprintln!("");
@@ -132,8 +148,6 @@ impl Camera {
         max_depth: i32,
         background_color: Vec3,
     ) -> Self {
-        let aspect_ratio = image_width as f32 / image_height as f32;
-
         let center = lookfrom;
 
         let sqrt_spp = samples_per_pixel.isqrt();
@@ -141,35 +155,16 @@ impl Camera {
         let pixel_samples_scale = 1.0 / (sqrt_spp * sqrt_spp) as f32;
         let recip_sqrt_spp = 1.0 / sqrt_spp as f32;
 
-        // Determine viewport dimensions
-        // let focal_length: f32 = (lookfrom - lookat).length();
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height: f32 = 2.0 * h * focus_dist;
-        let viewport_width = viewport_height * aspect_ratio;
-
         // Calculate the u, v, w unit basis vectors for the camera coordinate frame
         let w = (lookfrom - lookat).normalize(); // into camera from lookat
         let u = view_up.cross(w).normalize(); // to camera right
         let v = w.cross(u); // to camera up
 
-        // Calculate the vectors across the horizontal and down the vertical viewport edges
-        let viewport_u: Vec3 = viewport_width * u;
-        let viewport_v: Vec3 = viewport_height * -v;
-
-        // Calculate the horizontal and vertical delta vectors from pixel to pixel
-        let pixel_delta_u: Vec3 = viewport_u / image_width as f32;
-        let pixel_delta_v: Vec3 = viewport_v / image_height as f32;
-
-        // Calculate the location of the upper left pixel
-        let viewport_upper_left =
-            center - focus_dist * w - viewport_u / 2 as f32 - viewport_v / 2 as f32;
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
-
-        // Calculate the camera defocus disk basis vectors
-        let defocus_radius = focus_dist * (defocus_angle / 2.0).to_radians().tan();
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
+        let projection = Arc::new(PerspectiveProjection::with_defocus(
+            vfov,
+            defocus_angle,
+            focus_dist,
+        ));
 
         Self {
             image_width,
@@ -186,38 +181,162 @@ impl Camera {
             recip_sqrt_spp,
             max_depth,
             background_color,
-            pixel00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
-            // u,
-            // v,
-            // w,
-            defocus_angle,
-            // focus_dist,
-            defocus_disk_u,
-            defocus_disk_v,
+            u,
+            v,
+            w,
+            projection,
+            aperture: Arc::new(CircularAperture),
+            cats_eye: 0.0,
+            anamorphic_squeeze: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            exposure: 1.0,
         }
     }
 
-    // Construct a camera ray originating from the defocus disk and directed at a randomly sampled point around the pixel location x, y
-    // for stratified sample square s_x, s_y
+    pub fn image_width(&self) -> u32 {
+        self.image_width
+    }
+
+    pub fn image_height(&self) -> u32 {
+        self.image_height
+    }
+
+    pub fn projection(&self) -> Arc<dyn Projection> {
+        self.projection.clone()
+    }
+
+    // Replaces the perspective projection from new, keeping the camera placement
+    pub fn with_projection(mut self, projection: Arc<dyn Projection>) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: Arc<dyn Aperture>) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // Mechanical vignetting: off axis, only the part of the aperture inside a second circle
+    // shifted towards the image center lets light through, up to cats_eye lens radii at the
+    // corners. The clipped samples darken the image edges as in a real lens
+    pub fn with_cats_eye(mut self, cats_eye: f32) -> Self {
+        self.cats_eye = cats_eye;
+        self
+    }
+
+    // Anamorphic lenses squeeze the aperture horizontally, making tall oval bokeh
+    pub fn with_anamorphic_squeeze(mut self, squeeze: f32) -> Self {
+        self.anamorphic_squeeze = squeeze;
+        self
+    }
+
+    // Equal times for a frozen frame
+    pub fn with_shutter(mut self, shutter_open: f32, shutter_close: f32) -> Self {
+        self.shutter_open = shutter_open;
+        self.shutter_close = shutter_close;
+        self
+    }
+
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    // Depth along the view direction of the first surface through the center of pixel x, y,
+    // seen from the center of the lens
+    pub fn focus_distance_at(&self, world: &impl Hittable, x: u32, y: u32) -> Option<f32> {
+        let image_size = Vec2::new(self.image_width as f32, self.image_height as f32);
+        let film = (Vec2::new(x as f32, y as f32) + 0.5) / image_size;
+        let (origin, direction, _) =
+            self.projection
+                .generate_ray(film, Vec2::ZERO, image_size.x / image_size.y)?;
+        let (origin, direction) = self.to_world((origin, direction));
+
+        let ray = Ray::with_time(origin, direction, self.shutter_open);
+        let hit_record = world.hit(ray, Interval::new(0.001, f32::INFINITY), &mut rand::rng())?;
+
+        Some((hit_record.point - self.center).dot(-self.w))
+    }
+
+    // Construct a camera ray through a randomly sampled point around the pixel location x, y
+    // for stratified sample square s_x, s_y, from a random point on the lens
     // Stratify pixel into sqrt_spp x sqrt_spp square grid
-    fn get_ray(&self, x: u32, y: u32, s_x: u32, s_y: u32, rng: &mut impl Rng) -> Ray {
+    // Returns the ray with the projection's weight for the sample
+    fn get_ray(
+        &self,
+        x: u32,
+        y: u32,
+        s_x: u32,
+        s_y: u32,
+        rng: &mut impl Rng,
+    ) -> Option<(Ray, f32)> {
         // let offset = Self::sample_square(rng);
         let offset = self.sample_square_stratified(s_x, s_y, rng);
-        let pixel_sample = self.pixel00_loc
-            + (x as f32 + offset.x) * self.pixel_delta_u
-            + (y as f32 + offset.y) * self.pixel_delta_v;
-
-        // let ray_origin = self.center;
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
-        } else {
-            self.defocus_disk_sample(rng)
-        };
-        let ray_direction = pixel_sample - ray_origin;
+        let image_size = Vec2::new(self.image_width as f32, self.image_height as f32);
+        let film = (Vec2::new(x as f32, y as f32) + 0.5 + offset.truncate()) / image_size;
+        let lens = self.sample_lens(film, rng)?;
+        let aspect_ratio = image_size.x / image_size.y;
+
+        let (origin, direction, weight) = self.projection.generate_ray(film, lens, aspect_ratio)?;
+        let (ray_origin, ray_direction) = self.to_world((origin, direction));
+
+        // Differentials towards the neighbouring pixels through the same lens point, shrunk with
+        // the sample count since each sample covers less of the pixel
+        let differential_scale = self.recip_sqrt_spp.max(0.125);
+        let film_dx = Vec2::new(differential_scale / image_size.x, 0.0);
+        let film_dy = Vec2::new(0.0, differential_scale / image_size.y);
+        let differential = self
+            .projection
+            .generate_ray(film + film_dx, lens, aspect_ratio)
+            .zip(
+                self.projection
+                    .generate_ray(film + film_dy, lens, aspect_ratio),
+            )
+            .map(
+                |((rx_origin, rx_direction, _), (ry_origin, ry_direction, _))| {
+                    let (rx_origin, rx_direction) = self.to_world((rx_origin, rx_direction));
+                    let (ry_origin, ry_direction) = self.to_world((ry_origin, ry_direction));
+                    RayDifferential {
+                        rx_origin,
+                        rx_direction,
+                        ry_origin,
+                        ry_direction,
+                    }
+                },
+            );
+
+        let ray_time =
+            self.shutter_open + rng.random::<f32>() * (self.shutter_close - self.shutter_open);
+
+        Some((
+            Ray::with_differential(ray_origin, ray_direction, ray_time, differential),
+            weight,
+        ))
+    }
+
+    // Point on the unit lens for film position film, None where vignetting blocks it
+    fn sample_lens(&self, film: Vec2, rng: &mut impl Rng) -> Option<Vec2> {
+        let mut lens = self.aperture.sample(rng);
+        lens.x /= self.anamorphic_squeeze;
+
+        if self.cats_eye > 0.0 {
+            // outwards from the image center in camera space, unit length at the corners
+            let image_size = Vec2::new(self.image_width as f32, self.image_height as f32);
+            let off_axis =
+                Vec2::new(film.x - 0.5, 0.5 - film.y) * image_size / (0.5 * image_size.length());
+            if lens.distance_squared(-self.cats_eye * off_axis) > 1.0 {
+                return None;
+            }
+        }
+
+        Some(lens)
+    }
 
-        Ray::new(ray_origin, ray_direction)
+    // camera space origin and direction to world space
+    fn to_world(&self, (origin, direction): (Vec3, Vec3)) -> (Vec3, Vec3) {
+        let basis = |p: Vec3| p.x * self.u + p.y * self.v + p.z * self.w;
+        (self.center + basis(origin), basis(direction))
     }
 
     // random point in subpixel in stratified grid unit square [-0.5, -0.5]-[+0.5, +0.5]
@@ -238,12 +357,6 @@ impl Camera {
         )
     }
 
-    // random point in the camera defocus disk
-    fn defocus_disk_sample(&self, rng: &mut impl Rng) -> Vec3 {
-        let point = random_in_unit_disk(rng);
-        self.center + (point.x * self.defocus_disk_u) + (point.y * self.defocus_disk_v)
-    }
-
     fn ray_color(
         &self,
         ray: Ray,
@@ -256,14 +369,15 @@ impl Camera {
             return Vec3::ZERO;
         }
 
-        let Some(hit_record) = world.hit(ray, Interval::new(0.001, f32::INFINITY), rng) else {
+        let Some(mut hit_record) = world.hit(ray, Interval::new(0.001, f32::INFINITY), rng) else {
             return self.background_color;
         };
+        hit_record.compute_differentials(ray);
 
         let emitted_color =
             hit_record
                 .material
-                .emitted(&hit_record, hit_record.uv, hit_record.point);
+                .emitted(ray, &hit_record, hit_record.uv, hit_record.point);
 
         let Some(scatter_record) = hit_record.material.scatter(ray, &hit_record, rng) else {
             return emitted_color;
@@ -274,25 +388,32 @@ impl Camera {
                 let lights_pdf = Arc::new(HittablePdf::new(lights.clone(), hit_record.point));
                 let mixture_pdf = MixturePdf::new(lights_pdf, scatter_pdf);
 
-                let scattered_ray = Ray::new(hit_record.point, mixture_pdf.generate(rng));
+                let scattered_ray =
+                    Ray::with_time(hit_record.point, mixture_pdf.generate(rng), ray.time);
+                if !hit_record.is_consistent(scattered_ray.direction) {
+                    return emitted_color;
+                }
                 let pdf_value = mixture_pdf.value(scattered_ray.direction, rng);
 
                 if !pdf_value.is_finite() || pdf_value <= 0.0 {
                     return emitted_color;
                 }
 
-                let scattering_pdf =
+                let scattering_value =
                     hit_record
                         .material
-                        .scattering_pdf(ray, &hit_record, scattered_ray);
+                        .scattering_value(ray, &hit_record, scattered_ray);
 
                 let sample_color = self.ray_color(scattered_ray, depth - 1, world, lights, rng);
                 let scatter_color =
-                    (scatter_record.attenuation * scattering_pdf * sample_color) / pdf_value;
+                    (scatter_record.attenuation * scattering_value * sample_color) / pdf_value;
 
                 emitted_color + scatter_color
             }
             Either::Right(skip_pdf_ray) => {
+                if !hit_record.is_consistent(skip_pdf_ray.direction) {
+                    return emitted_color;
+                }
                 scatter_record.attenuation
                     * self.ray_color(skip_pdf_ray, depth - 1, world, lights, rng)
             }
@@ -9,6 +9,20 @@ fn linear_to_gamma(linear: f32) -> f32 {
     0.0
 }
 
+// Rec. 709 relative luminance of a linear color
+pub fn luminance(color: Vec3) -> f32 {
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+// sRGB transfer function, decodes an encoded value in [0, 1] to linear
+pub fn srgb_to_linear(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 #[inline]
 pub fn vec3_to_rgb8(color_vec: Vec3) -> image::Rgb<u8> {
     let mut r = linear_to_gamma(color_vec.x);
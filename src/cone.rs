@@ -0,0 +1,203 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use glam::{Vec2, Vec3};
+use rand::{Rng, RngCore};
+
+use crate::{
+    aabb::Aabb,
+    cylinder::SurfacePart,
+    hit::{HitRecord, Hittable, uniform_area_pdf_value},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    util::random_in_unit_disk,
+};
+
+#[derive(Clone, Debug)]
+pub struct Cone {
+    pub base: Vec3,
+    pub height: f32,
+    pub radius: f32, // at the base
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+    frame: Onb, // w along the axis from base to apex
+    bbox: Aabb,
+}
+
+impl Cone {
+    // Open cone from the center of its base circle to the apex
+    pub fn new(base: Vec3, apex: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
+        let frame = Onb::new(apex - base);
+        let extent = radius * (Vec3::ONE - frame.w * frame.w).max(Vec3::ZERO).powf(0.5);
+
+        Self {
+            base,
+            height: (apex - base).length(),
+            radius,
+            capped: false,
+            material,
+            frame,
+            bbox: Aabb::merged(
+                Aabb::from_corners(base - extent, base + extent),
+                Aabb::from_corners(apex, apex),
+            ),
+        }
+    }
+
+    // Closed with a disk at the base
+    pub fn capped(base: Vec3, apex: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
+        Self {
+            capped: true,
+            ..Self::new(base, apex, radius, material)
+        }
+    }
+
+    pub fn side_area(&self) -> f32 {
+        PI * self.radius * self.radius.hypot(self.height)
+    }
+
+    pub fn area(&self) -> f32 {
+        if self.capped {
+            self.side_area() + PI * self.radius * self.radius
+        } else {
+            self.side_area()
+        }
+    }
+
+    // point is in the cone frame, relative to the base
+    // Side u: [0,1] of angle around the axis, v: [0,1] from base to apex
+    // Cap u: [0,1] of angle around the axis, v: [0,1] from the center to the rim
+    fn hit_record(&self, ray: Ray, point: Vec3, t: f32, part: SurfacePart) -> HitRecord {
+        let phi = point.y.atan2(point.x).rem_euclid(2.0 * PI);
+        let r = point.truncate().length();
+        let dpdu = 2.0 * PI * Vec3::new(-point.y, point.x, 0.0);
+
+        let (normal, uv, dpdv) = if part == SurfacePart::Side {
+            // gradient of x^2 + y^2 - (r / h)^2 (h - z)^2, along the axis at the apex,
+            // and towards the apex along the side
+            let slope = self.radius / self.height;
+            let normal = Vec3::new(point.x, point.y, slope * slope * (self.height - point.z))
+                .try_normalize()
+                .unwrap_or(Vec3::Z);
+            let v = point.z / self.height;
+            let dpdv = if r > 0.0 {
+                Vec3::new(
+                    -point.x / r * self.radius,
+                    -point.y / r * self.radius,
+                    self.height,
+                )
+            } else {
+                Vec3::new(0.0, 0.0, self.height)
+            };
+            (normal, Vec2::new(phi / (2.0 * PI), v), dpdv)
+        } else {
+            let dpdv = if r > 0.0 {
+                point.with_z(0.0) / r * self.radius
+            } else {
+                Vec3::ZERO
+            };
+            (
+                Vec3::NEG_Z,
+                Vec2::new(phi / (2.0 * PI), r / self.radius),
+                dpdv,
+            )
+        };
+
+        let mut hit_record = HitRecord::new(
+            ray,
+            ray.at(t),
+            self.frame.transform(normal),
+            self.material.clone(),
+            t,
+            uv,
+        );
+        hit_record.dpdu = self.frame.transform(dpdu);
+        hit_record.dpdv = self.frame.transform(dpdv);
+        hit_record
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let origin = self.frame.to_local(ray.origin - self.base);
+        let direction = self.frame.to_local(ray.direction);
+
+        let mut candidates = [(f32::INFINITY, SurfacePart::Side); 3];
+        let mut count = 0;
+
+        // x^2 + y^2 = k (h - z)^2 between base and apex
+        let k = (self.radius / self.height).powi(2);
+        let apex_offset = self.height - origin.z;
+        let a =
+            direction.x * direction.x + direction.y * direction.y - k * direction.z * direction.z;
+        let h = -(origin.x * direction.x + origin.y * direction.y + k * direction.z * apex_offset);
+        let c = origin.x * origin.x + origin.y * origin.y - k * apex_offset * apex_offset;
+        let discriminant = h * h - a * c;
+        let roots = if a.abs() < 1e-12 {
+            // parallel to the side, a single crossing
+            [c / (2.0 * h), f32::NAN]
+        } else if discriminant < 0.0 {
+            [f32::NAN; 2]
+        } else {
+            [(h - discriminant.sqrt()) / a, (h + discriminant.sqrt()) / a]
+        };
+        for root in roots {
+            // the other nappe of the double cone lies above the apex
+            let z = origin.z + root * direction.z;
+            if root.is_finite() && (0.0..=self.height).contains(&z) {
+                candidates[count] = (root, SurfacePart::Side);
+                count += 1;
+            }
+        }
+
+        if self.capped && direction.z != 0.0 {
+            let root = -origin.z / direction.z;
+            let point = origin + root * direction;
+            if point.truncate().length_squared() <= self.radius * self.radius {
+                candidates[count] = (root, SurfacePart::BottomCap);
+                count += 1;
+            }
+        }
+
+        let candidates = &mut candidates[..count];
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+        candidates
+            .iter()
+            .copied()
+            .filter(|&(root, _)| ray_t.surrounds(root))
+            .map(|(root, part)| {
+                let mut point = origin + root * direction;
+                if part == SurfacePart::BottomCap {
+                    point.z = 0.0;
+                }
+                self.hit_record(ray, point, root, part)
+            })
+            .find(|hit_record| self.material.alpha_test(hit_record, rng))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut dyn RngCore) -> f32 {
+        uniform_area_pdf_value(self, self.area(), origin, direction, rng)
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = if rng.random::<f32>() * self.area() < self.side_area() {
+            // the side's circumference shrinks linearly towards the apex
+            let from_apex = rng.random::<f32>().sqrt();
+            let phi = 2.0 * PI * rng.random::<f32>();
+            Vec3::new(
+                from_apex * self.radius * phi.cos(),
+                from_apex * self.radius * phi.sin(),
+                (1.0 - from_apex) * self.height,
+            )
+        } else {
+            (self.radius * random_in_unit_disk(rng)).extend(0.0)
+        };
+
+        self.base + self.frame.transform(point) - origin
+    }
+}
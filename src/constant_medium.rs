@@ -68,14 +68,14 @@ impl Hittable for ConstantMedium {
 
         let t = hit_record1.t + hit_distance / ray_length;
 
-        Some(HitRecord {
-            point: ray.at(t),
-            normal: Vec3::ONE, // arbitrary
-            material: self.phase_function.clone(),
+        Some(HitRecord::new(
+            ray,
+            ray.at(t),
+            Vec3::ONE, // arbitrary
+            self.phase_function.clone(),
             t,
-            uv: Vec2::ZERO,
-            front_face: true,
-        })
+            Vec2::ZERO,
+        ))
     }
 
     fn bounding_box(&self) -> Aabb {
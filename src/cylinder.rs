@@ -0,0 +1,197 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use glam::{Vec2, Vec3};
+use rand::{Rng, RngCore};
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable, uniform_area_pdf_value},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    util::random_in_unit_disk,
+};
+
+// The parts of a cylinder or cone a ray can hit
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SurfacePart {
+    Side,
+    BottomCap,
+    TopCap,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub height: f32,
+    pub radius: f32,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+    frame: Onb, // w along the axis from base to top
+    bbox: Aabb,
+}
+
+impl Cylinder {
+    // Open tube between the centers of its ends
+    pub fn new(base: Vec3, top: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
+        let frame = Onb::new(top - base);
+        let extent = radius * (Vec3::ONE - frame.w * frame.w).max(Vec3::ZERO).powf(0.5);
+
+        Self {
+            base,
+            height: (top - base).length(),
+            radius,
+            capped: false,
+            material,
+            frame,
+            bbox: Aabb::from_corners(base.min(top) - extent, base.max(top) + extent),
+        }
+    }
+
+    // Closed with a disk at each end
+    pub fn capped(base: Vec3, top: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
+        Self {
+            capped: true,
+            ..Self::new(base, top, radius, material)
+        }
+    }
+
+    pub fn side_area(&self) -> f32 {
+        2.0 * PI * self.radius * self.height
+    }
+
+    pub fn area(&self) -> f32 {
+        if self.capped {
+            self.side_area() + 2.0 * PI * self.radius * self.radius
+        } else {
+            self.side_area()
+        }
+    }
+
+    // point is in the cylinder frame, relative to the base
+    // Side u: [0,1] of angle around the axis, v: [0,1] from base to top
+    // Caps u: [0,1] of angle around the axis, v: [0,1] from the center to the rim
+    fn hit_record(&self, ray: Ray, point: Vec3, t: f32, part: SurfacePart) -> HitRecord {
+        let phi = point.y.atan2(point.x).rem_euclid(2.0 * PI);
+        let r = point.truncate().length();
+        let dpdu = 2.0 * PI * Vec3::new(-point.y, point.x, 0.0);
+
+        let (normal, uv, dpdv) = match part {
+            SurfacePart::Side => (
+                Vec3::new(point.x, point.y, 0.0) / self.radius,
+                Vec2::new(phi / (2.0 * PI), point.z / self.height),
+                Vec3::new(0.0, 0.0, self.height),
+            ),
+            SurfacePart::BottomCap | SurfacePart::TopCap => (
+                if part == SurfacePart::TopCap {
+                    Vec3::Z
+                } else {
+                    Vec3::NEG_Z
+                },
+                Vec2::new(phi / (2.0 * PI), r / self.radius),
+                if r > 0.0 {
+                    point.with_z(0.0) / r * self.radius
+                } else {
+                    Vec3::ZERO
+                },
+            ),
+        };
+
+        let mut hit_record = HitRecord::new(
+            ray,
+            ray.at(t),
+            self.frame.transform(normal),
+            self.material.clone(),
+            t,
+            uv,
+        );
+        hit_record.dpdu = self.frame.transform(dpdu);
+        hit_record.dpdv = self.frame.transform(dpdv);
+        hit_record
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let origin = self.frame.to_local(ray.origin - self.base);
+        let direction = self.frame.to_local(ray.direction);
+
+        let mut candidates = [(f32::INFINITY, SurfacePart::Side); 4];
+        let mut count = 0;
+
+        // x^2 + y^2 = r^2 between the ends
+        let a = direction.x * direction.x + direction.y * direction.y;
+        let h = -(origin.x * direction.x + origin.y * direction.y);
+        let c = origin.x * origin.x + origin.y * origin.y - self.radius * self.radius;
+        let discriminant = h * h - a * c;
+        if a > 0.0 && discriminant >= 0.0 {
+            for root in [(h - discriminant.sqrt()) / a, (h + discriminant.sqrt()) / a] {
+                let z = origin.z + root * direction.z;
+                if (0.0..=self.height).contains(&z) {
+                    candidates[count] = (root, SurfacePart::Side);
+                    count += 1;
+                }
+            }
+        }
+
+        if self.capped && direction.z != 0.0 {
+            for (z, part) in [
+                (0.0, SurfacePart::BottomCap),
+                (self.height, SurfacePart::TopCap),
+            ] {
+                let root = (z - origin.z) / direction.z;
+                let point = origin + root * direction;
+                if point.truncate().length_squared() <= self.radius * self.radius {
+                    candidates[count] = (root, part);
+                    count += 1;
+                }
+            }
+        }
+
+        let candidates = &mut candidates[..count];
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+        candidates
+            .iter()
+            .copied()
+            .filter(|&(root, _)| ray_t.surrounds(root))
+            .map(|(root, part)| {
+                let mut point = origin + root * direction;
+                match part {
+                    SurfacePart::BottomCap => point.z = 0.0,
+                    SurfacePart::TopCap => point.z = self.height,
+                    SurfacePart::Side => {}
+                }
+                self.hit_record(ray, point, root, part)
+            })
+            .find(|hit_record| self.material.alpha_test(hit_record, rng))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut dyn RngCore) -> f32 {
+        uniform_area_pdf_value(self, self.area(), origin, direction, rng)
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = if rng.random::<f32>() * self.area() < self.side_area() {
+            let phi = 2.0 * PI * rng.random::<f32>();
+            Vec3::new(
+                self.radius * phi.cos(),
+                self.radius * phi.sin(),
+                self.height * rng.random::<f32>(),
+            )
+        } else {
+            let z = if rng.random::<bool>() {
+                self.height
+            } else {
+                0.0
+            };
+            (self.radius * random_in_unit_disk(rng)).extend(z)
+        };
+
+        self.base + self.frame.transform(point) - origin
+    }
+}
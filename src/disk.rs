@@ -0,0 +1,104 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use glam::{Vec2, Vec3};
+use rand::RngCore;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable, uniform_area_pdf_value},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+    util::random_in_unit_disk,
+};
+
+#[derive(Clone, Debug)]
+pub struct Disk {
+    pub center: Vec3,
+    pub radius: f32,
+    pub material: Arc<dyn Material>,
+    frame: Onb, // w is the normal
+    bbox: Aabb,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
+        let frame = Onb::new(normal);
+        let extent = radius * (Vec3::ONE - frame.w * frame.w).max(Vec3::ZERO).powf(0.5);
+
+        Self {
+            center,
+            radius,
+            material,
+            frame,
+            bbox: Aabb::from_corners(center - extent, center + extent),
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.frame.w
+    }
+
+    pub fn area(&self) -> f32 {
+        PI * self.radius * self.radius
+    }
+
+    // u: [0,1] of angle around the normal, v: [0,1] from the center to the rim
+    // point is relative to the center, in the disk frame
+    fn hit_record(&self, ray: Ray, point: Vec3, t: f32) -> HitRecord {
+        let r = point.truncate().length();
+        let phi = point.y.atan2(point.x).rem_euclid(2.0 * PI);
+        let uv = Vec2::new(phi / (2.0 * PI), r / self.radius);
+
+        let mut hit_record =
+            HitRecord::new(ray, ray.at(t), self.frame.w, self.material.clone(), t, uv);
+        hit_record.dpdu = self
+            .frame
+            .transform(2.0 * PI * Vec3::new(-point.y, point.x, 0.0));
+        if r > 0.0 {
+            hit_record.dpdv = self.frame.transform(point / r * self.radius);
+        }
+        hit_record
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let origin = self.frame.to_local(ray.origin - self.center);
+        let direction = self.frame.to_local(ray.direction);
+
+        // ray is parallel to plane
+        if direction.z.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = -origin.z / direction.z;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let point = origin + t * direction;
+        if point.truncate().length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        let hit_record = self.hit_record(ray, point.with_z(0.0), t);
+        self.material
+            .alpha_test(&hit_record, rng)
+            .then_some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut dyn RngCore) -> f32 {
+        uniform_area_pdf_value(self, self.area(), origin, direction, rng)
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let point = self.radius * random_in_unit_disk(rng);
+        self.center + self.frame.transform(point.extend(0.0)) - origin
+    }
+}
@@ -0,0 +1,678 @@
+use std::{collections::HashMap, f32::consts::PI, fmt::Debug, path::Path, sync::Arc};
+
+use anyhow::Context;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+use crate::{
+    camera::Camera,
+    disk::Disk,
+    hit::Hittable,
+    hittable_list::HittableList,
+    material::{
+        AlphaMaskedMaterial, AlphaMode, DiffuseLightMaterial, Material, NormalMap,
+        NormalMappedMaterial, PrincipledMaterial, SpotLightMaterial,
+    },
+    mesh::{IndexedMesh, triangle_mesh_from_faces},
+    projection::OrthographicProjection,
+    sphere::Sphere,
+    texture::{ImageTexture, SolidColor, Texture, TextureFilter, TextureUsage, WrapMode},
+    texture_graph::{Channel, ChannelTexture, MathOperation, MathTexture},
+    transform::Transform,
+};
+
+// Punctual lights become small emissive spheres of this radius, in scene units
+pub const DEFAULT_LIGHT_RADIUS: f32 = 0.05;
+
+// Directional lights become distant disks of the sun's angular radius, this many times the scene
+// size away
+const DIRECTIONAL_LIGHT_ANGULAR_RADIUS: f32 = 0.00465;
+const DIRECTIONAL_LIGHT_DISTANCE: f32 = 100.0;
+
+#[derive(Debug)]
+pub struct GltfScene {
+    pub objects: HittableList,
+    pub lights: HittableList, // the punctual lights again, for light sampling
+    pub cameras: Vec<GltfCamera>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GltfProjection {
+    Perspective {
+        vfov: f32, // degrees
+        aspect_ratio: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GltfCamera {
+    pub name: Option<String>,
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    pub view_up: Vec3,
+    pub projection: GltfProjection,
+}
+
+impl GltfCamera {
+    pub fn camera(
+        &self,
+        image_width: u32,
+        image_height: u32,
+        samples_per_pixel: u32,
+        max_depth: i32,
+        background_color: Vec3,
+    ) -> Camera {
+        let vfov = match self.projection {
+            // an image narrower than the camera's aspect ratio gets a wider vertical field of
+            // view, keeping all of the horizontal one in frame
+            GltfProjection::Perspective {
+                vfov,
+                aspect_ratio: Some(aspect_ratio),
+            } => {
+                let image_aspect_ratio = image_width as f32 / image_height as f32;
+                if image_aspect_ratio < aspect_ratio {
+                    let half_width = aspect_ratio * (vfov.to_radians() / 2.0).tan();
+                    2.0 * (half_width / image_aspect_ratio).atan().to_degrees()
+                } else {
+                    vfov
+                }
+            }
+            GltfProjection::Perspective { vfov, .. } => vfov,
+            GltfProjection::Orthographic { .. } => 90.0,
+        };
+
+        let camera = Camera::new(
+            image_width,
+            image_height,
+            vfov,
+            self.lookfrom,
+            self.lookat,
+            self.view_up,
+            0.0,
+            1.0,
+            samples_per_pixel,
+            max_depth,
+            background_color,
+        );
+
+        match self.projection {
+            GltfProjection::Perspective { .. } => camera,
+            // xmag and ymag are half extents, fitted in the image like the perspective fov
+            GltfProjection::Orthographic { xmag, ymag } => {
+                let image_aspect_ratio = image_width as f32 / image_height as f32;
+                let half_height = ymag.max(xmag / image_aspect_ratio);
+                camera.with_projection(Arc::new(OrthographicProjection::new(2.0 * half_height)))
+            }
+        }
+    }
+}
+
+pub fn load_gltf(path: impl AsRef<Path> + Debug) -> anyhow::Result<GltfScene> {
+    load_gltf_with_light_radius(path, DEFAULT_LIGHT_RADIUS)
+}
+
+// Loads the default scene, or the first one, of a .gltf or .glb file
+pub fn load_gltf_with_light_radius(
+    path: impl AsRef<Path> + Debug,
+    light_radius: f32,
+) -> anyhow::Result<GltfScene> {
+    let (document, buffers, images) =
+        gltf::import(&path).with_context(|| format!("Failed to import glTF file {path:?}."))?;
+
+    let mut texture_cache = GltfTextureCache::new(&images);
+    let materials = document
+        .materials()
+        .map(|material| translate_gltf_material(&material, &mut texture_cache))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let default_material: Arc<dyn Material> = Arc::new(default_gltf_material());
+
+    let meshes = document
+        .meshes()
+        .map(|mesh| load_gltf_mesh(&mesh, &buffers, &materials, &default_material))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut scene = GltfScene {
+        objects: HittableList::new(),
+        lights: HittableList::new(),
+        cameras: Vec::new(),
+    };
+
+    let gltf_scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .context("glTF file has no scenes.")?;
+    let mut directional_lights = Vec::new();
+    for node in gltf_scene.nodes() {
+        visit_node(
+            &node,
+            Mat4::IDENTITY,
+            &meshes,
+            light_radius,
+            &mut scene,
+            &mut directional_lights,
+        );
+    }
+    for light in directional_lights {
+        add_directional_light(light, &mut scene);
+    }
+
+    Ok(scene)
+}
+
+fn visit_node(
+    node: &gltf::Node,
+    parent_transform: Mat4,
+    meshes: &[Option<Arc<dyn Hittable>>],
+    light_radius: f32,
+    scene: &mut GltfScene,
+    directional_lights: &mut Vec<DirectionalLight>,
+) {
+    let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    // meshes are shared between the nodes that instance them
+    if let Some(mesh) = node.mesh()
+        && let Some(object) = &meshes[mesh.index()]
+    {
+        if transform == Mat4::IDENTITY {
+            scene.objects.add(object.clone());
+        } else {
+            scene
+                .objects
+                .add(Arc::new(Transform::new(object.clone(), &transform)));
+        }
+    }
+
+    if let Some(camera) = node.camera() {
+        let projection = match camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) => GltfProjection::Perspective {
+                vfov: perspective.yfov().to_degrees(),
+                aspect_ratio: perspective.aspect_ratio(),
+            },
+            gltf::camera::Projection::Orthographic(orthographic) => GltfProjection::Orthographic {
+                xmag: orthographic.xmag(),
+                ymag: orthographic.ymag(),
+            },
+        };
+
+        // glTF cameras look down -z with +y up
+        let lookfrom = transform.transform_point3(Vec3::ZERO);
+        scene.cameras.push(GltfCamera {
+            name: camera.name().map(str::to_owned),
+            lookfrom,
+            lookat: lookfrom + transform.transform_vector3(-Vec3::Z).normalize(),
+            view_up: transform.transform_vector3(Vec3::Y).normalize(),
+            projection,
+        });
+    }
+
+    if let Some(light) = node.light() {
+        add_punctual_light(&light, transform, light_radius, scene, directional_lights);
+    }
+
+    for child in node.children() {
+        visit_node(
+            &child,
+            transform,
+            meshes,
+            light_radius,
+            scene,
+            directional_lights,
+        );
+    }
+}
+
+// Intensity is taken as radiant intensity, a sphere of radius r and radiance L has an
+// intensity of pi r^2 L in every direction
+// Directional lights need the scene's size, so they are only collected here
+fn add_punctual_light(
+    light: &gltf::khr_lights_punctual::Light,
+    transform: Mat4,
+    light_radius: f32,
+    scene: &mut GltfScene,
+    directional_lights: &mut Vec<DirectionalLight>,
+) {
+    let color = Vec3::from(light.color());
+    // lights shine down -z
+    let direction = transform.transform_vector3(-Vec3::Z).normalize();
+
+    let diffuse_light = DiffuseLightMaterial::new(
+        Arc::new(SolidColor::new(color)),
+        light.intensity() / (PI * light_radius * light_radius),
+    );
+    let material: Arc<dyn Material> = match light.kind() {
+        gltf::khr_lights_punctual::Kind::Directional => {
+            directional_lights.push(DirectionalLight {
+                direction,
+                color,
+                illuminance: light.intensity(),
+            });
+            return;
+        }
+        gltf::khr_lights_punctual::Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => Arc::new(SpotLightMaterial::new(
+            diffuse_light,
+            direction,
+            inner_cone_angle,
+            outer_cone_angle,
+        )),
+        gltf::khr_lights_punctual::Kind::Point => Arc::new(diffuse_light),
+    };
+
+    let sphere = Arc::new(Sphere::new(
+        transform.transform_point3(Vec3::ZERO),
+        light_radius,
+        material,
+    ));
+    scene.objects.add(sphere.clone());
+    scene.lights.add(sphere);
+}
+
+#[derive(Clone, Copy, Debug)]
+struct DirectionalLight {
+    direction: Vec3, // the light travels along it
+    color: Vec3,
+    illuminance: f32,
+}
+
+// A disk the size of the sun in the sky, far enough away that its light is parallel over the
+// scene, with the radiance giving the light's illuminance
+// A disk of angular radius a and radiance L lights a facing surface with pi sin^2(a) L
+fn add_directional_light(light: DirectionalLight, scene: &mut GltfScene) {
+    let (min, max) = scene.objects.bounding_box().get_corners();
+    if !(min.is_finite() && max.is_finite()) {
+        eprintln!("Warning: glTF directional light skipped, the scene has no bounded objects.");
+        return;
+    }
+
+    let center = 0.5 * (min + max);
+    let distance = DIRECTIONAL_LIGHT_DISTANCE * (max - min).length().max(1.0);
+    let material = Arc::new(DiffuseLightMaterial::new(
+        Arc::new(SolidColor::new(light.color)),
+        light.illuminance / (PI * DIRECTIONAL_LIGHT_ANGULAR_RADIUS.sin().powi(2)),
+    ));
+    let disk = Arc::new(Disk::new(
+        center - distance * light.direction,
+        light.direction,
+        distance * DIRECTIONAL_LIGHT_ANGULAR_RADIUS.tan(),
+        material,
+    ));
+    scene.objects.add(disk.clone());
+    scene.lights.add(disk);
+}
+
+// All the triangle primitives of a mesh in one TriangleMesh, with a material slot each
+fn load_gltf_mesh(
+    mesh: &gltf::Mesh,
+    buffers: &[gltf::buffer::Data],
+    materials: &[Arc<dyn Material>],
+    default_material: &Arc<dyn Material>,
+) -> anyhow::Result<Option<Arc<dyn Hittable>>> {
+    let name = mesh.name().unwrap_or("unnamed");
+    let mut indexed_mesh = IndexedMesh::default();
+    let mut mesh_materials = Vec::new();
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            eprintln!(
+                "Warning: glTF mesh \"{name}\": {:?} primitives are not supported.",
+                primitive.mode()
+            );
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions: Vec<Vec3> = reader
+            .read_positions()
+            .with_context(|| format!("glTF mesh \"{name}\" has a primitive without positions."))?
+            .map(Vec3::from)
+            .collect();
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|index| index as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+        if indices.iter().any(|&index| index >= positions.len()) {
+            anyhow::bail!("glTF mesh \"{name}\" has an index out of range.");
+        }
+
+        eprintln!(
+            "Loading glTF mesh \"{name}\" with {} vertices and {} indices...",
+            positions.len(),
+            indices.len()
+        );
+
+        let vertex_start = indexed_mesh.positions.len();
+        let vertex_count = positions.len();
+        indexed_mesh.positions.extend(positions);
+
+        // missing normals are zero, which shades flat, as glTF asks for
+        append_attribute(
+            &mut indexed_mesh.normals,
+            reader
+                .read_normals()
+                .map(|normals| normals.map(Vec3::from).collect()),
+            vertex_start,
+            vertex_count,
+            Vec3::ZERO,
+        );
+        // glTF puts v = 0 at the top of the image, ImageTexture at the bottom
+        append_attribute(
+            &mut indexed_mesh.uvs,
+            reader
+                .read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)).collect()),
+            vertex_start,
+            vertex_count,
+            Vec2::ZERO,
+        );
+        append_attribute(
+            &mut indexed_mesh.tangents,
+            reader
+                .read_tangents()
+                .map(|tangents| tangents.map(Vec4::from).collect()),
+            vertex_start,
+            vertex_count,
+            Vec4::ZERO,
+        );
+
+        let material_index = mesh_materials.len() as u32;
+        mesh_materials.push(match primitive.material().index() {
+            Some(index) => materials[index].clone(),
+            None => default_material.clone(),
+        });
+        for face in indices.chunks_exact(3) {
+            indexed_mesh
+                .faces
+                .push([face[0], face[1], face[2]].map(|index| vertex_start + index));
+            indexed_mesh.material_indices.push(material_index);
+        }
+    }
+
+    if indexed_mesh.faces.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Arc::new(triangle_mesh_from_faces(
+        indexed_mesh,
+        0.0,
+        mesh_materials,
+    ))))
+}
+
+// Appends a primitive's vertex attribute to the mesh's, padding with default for the vertices of
+// primitives without it
+fn append_attribute<T: Copy>(
+    attribute: &mut Option<Vec<T>>,
+    values: Option<Vec<T>>,
+    vertex_start: usize,
+    vertex_count: usize,
+    default: T,
+) {
+    if attribute.is_none() && values.is_some() {
+        *attribute = Some(vec![default; vertex_start]);
+    }
+    if let Some(attribute) = attribute {
+        attribute.extend(values.into_iter().flatten().take(vertex_count));
+        attribute.resize(vertex_start + vertex_count, default);
+    }
+}
+
+// The glTF default material, white, fully metallic and fully rough
+fn default_gltf_material() -> PrincipledMaterial {
+    let mut material = PrincipledMaterial::new(Arc::new(SolidColor::splat(1.0)));
+    material.metallic = Arc::new(SolidColor::splat(1.0));
+    material.roughness = Arc::new(SolidColor::splat(1.0));
+    material
+}
+
+// Multiplies a texture by a constant factor, leaving it alone for a factor of one
+fn scaled(texture: Arc<dyn Texture>, factor: Vec3) -> Arc<dyn Texture> {
+    if factor == Vec3::ONE {
+        return texture;
+    }
+    Arc::new(MathTexture::new(
+        MathOperation::Multiply,
+        texture,
+        Arc::new(SolidColor::new(factor)),
+    ))
+}
+
+// Translates metallic-roughness materials and the transmission, ior and emissive strength
+// extensions into a PrincipledMaterial
+// Occlusion maps are ignored since occlusion comes out of the path tracing itself
+fn translate_gltf_material(
+    gltf_material: &gltf::Material,
+    texture_cache: &mut GltfTextureCache,
+) -> anyhow::Result<Arc<dyn Material>> {
+    let name = gltf_material.name().unwrap_or("unnamed");
+    let pbr = gltf_material.pbr_metallic_roughness();
+    let mut material = default_gltf_material();
+
+    let base_color_factor = Vec4::from(pbr.base_color_factor());
+    material.base_color = match pbr.base_color_texture() {
+        Some(info) => scaled(
+            texture_cache.load(name, &info.texture(), info.tex_coord(), TextureUsage::Color)?,
+            base_color_factor.truncate(),
+        ),
+        None => Arc::new(SolidColor::new(base_color_factor.truncate())),
+    };
+
+    // roughness in green and metallic in blue
+    let metallic_factor = Vec3::splat(pbr.metallic_factor());
+    let roughness_factor = Vec3::splat(pbr.roughness_factor());
+    match pbr.metallic_roughness_texture() {
+        Some(info) => {
+            let texture =
+                texture_cache.load(name, &info.texture(), info.tex_coord(), TextureUsage::Data)?;
+            material.roughness = scaled(
+                Arc::new(ChannelTexture::new(texture.clone(), Channel::Green)),
+                roughness_factor,
+            );
+            material.metallic = scaled(
+                Arc::new(ChannelTexture::new(texture, Channel::Blue)),
+                metallic_factor,
+            );
+        }
+        None => {
+            material.roughness = Arc::new(SolidColor::new(roughness_factor));
+            material.metallic = Arc::new(SolidColor::new(metallic_factor));
+        }
+    }
+
+    if let Some(transmission) = gltf_material.transmission() {
+        let factor = Vec3::splat(transmission.transmission_factor());
+        material.transmission = match transmission.transmission_texture() {
+            Some(info) => scaled(
+                Arc::new(ChannelTexture::new(
+                    texture_cache.load(
+                        name,
+                        &info.texture(),
+                        info.tex_coord(),
+                        TextureUsage::Data,
+                    )?,
+                    Channel::Red,
+                )),
+                factor,
+            ),
+            None => Arc::new(SolidColor::new(factor)),
+        };
+    }
+    if let Some(refraction_index) = gltf_material.ior() {
+        material.refraction_index = refraction_index;
+    }
+
+    let emissive_factor = Vec3::from(gltf_material.emissive_factor());
+    if emissive_factor != Vec3::ZERO {
+        material.emission = match gltf_material.emissive_texture() {
+            Some(info) => scaled(
+                texture_cache.load(name, &info.texture(), info.tex_coord(), TextureUsage::Color)?,
+                emissive_factor,
+            ),
+            None => Arc::new(SolidColor::new(emissive_factor)),
+        };
+        material.emission_strength = gltf_material.emissive_strength().unwrap_or(1.0);
+    }
+
+    let mut material: Arc<dyn Material> = Arc::new(material);
+
+    let alpha_mode = match gltf_material.alpha_mode() {
+        gltf::material::AlphaMode::Opaque => None,
+        gltf::material::AlphaMode::Mask => Some(AlphaMode::Threshold(
+            gltf_material.alpha_cutoff().unwrap_or(0.5),
+        )),
+        gltf::material::AlphaMode::Blend => Some(AlphaMode::Stochastic),
+    };
+    if let Some(alpha_mode) = alpha_mode {
+        let alpha_factor = Vec3::splat(base_color_factor.w);
+        let opacity = match pbr.base_color_texture() {
+            Some(info) => scaled(
+                texture_cache.load(name, &info.texture(), info.tex_coord(), TextureUsage::Alpha)?,
+                alpha_factor,
+            ),
+            None => Arc::new(SolidColor::new(alpha_factor)),
+        };
+        material = Arc::new(AlphaMaskedMaterial::with_mode(
+            material, opacity, alpha_mode,
+        ));
+    }
+
+    if let Some(normal_texture) = gltf_material.normal_texture() {
+        let texture = texture_cache.load(
+            name,
+            &normal_texture.texture(),
+            normal_texture.tex_coord(),
+            TextureUsage::Data,
+        )?;
+        material = Arc::new(NormalMappedMaterial::new(
+            material,
+            NormalMap::TangentSpace {
+                texture,
+                strength: normal_texture.scale(),
+            },
+        ));
+    }
+
+    Ok(material)
+}
+
+struct GltfTextureCache<'a> {
+    images: &'a [gltf::image::Data],
+    textures: HashMap<(usize, TextureUsage), Arc<ImageTexture>>,
+}
+
+impl<'a> GltfTextureCache<'a> {
+    fn new(images: &'a [gltf::image::Data]) -> Self {
+        Self {
+            images,
+            textures: HashMap::new(),
+        }
+    }
+
+    fn load(
+        &mut self,
+        material_name: &str,
+        texture: &gltf::Texture,
+        tex_coord: u32,
+        usage: TextureUsage,
+    ) -> anyhow::Result<Arc<dyn Texture>> {
+        if tex_coord != 0 {
+            eprintln!(
+                "Warning: glTF material \"{material_name}\": texture coordinate set {tex_coord} \
+                 not supported, using set 0."
+            );
+        }
+
+        let key = (texture.index(), usage);
+        if let Some(image_texture) = self.textures.get(&key) {
+            return Ok(image_texture.clone());
+        }
+
+        let data = &self.images[texture.source().index()];
+        let mut image_texture = ImageTexture::from_image_for_usage(gltf_image(data)?, usage);
+
+        let sampler = texture.sampler();
+        image_texture.wrap_u = gltf_wrap_mode(sampler.wrap_s());
+        image_texture.wrap_v = gltf_wrap_mode(sampler.wrap_t());
+        if sampler.mag_filter() == Some(gltf::texture::MagFilter::Nearest) {
+            image_texture.filter = TextureFilter::Nearest;
+        }
+
+        let image_texture = Arc::new(image_texture);
+        self.textures.insert(key, image_texture.clone());
+        Ok(image_texture)
+    }
+}
+
+fn gltf_wrap_mode(mode: gltf::texture::WrappingMode) -> WrapMode {
+    match mode {
+        gltf::texture::WrappingMode::Repeat => WrapMode::Repeat,
+        gltf::texture::WrappingMode::MirroredRepeat => WrapMode::Mirror,
+        gltf::texture::WrappingMode::ClampToEdge => WrapMode::Clamp,
+    }
+}
+
+// Decoded glTF pixels into an image, two channel formats get an empty blue channel
+fn gltf_image(data: &gltf::image::Data) -> anyhow::Result<image::DynamicImage> {
+    use gltf::image::Format;
+
+    let (width, height) = (data.width, data.height);
+    let words = || -> Vec<u16> {
+        data.pixels
+            .chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect()
+    };
+    let floats = || -> Vec<f32> {
+        data.pixels
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    };
+
+    let image = match data.format {
+        Format::R8 => {
+            image::GrayImage::from_raw(width, height, data.pixels.clone()).map(Into::into)
+        }
+        Format::R8G8 => {
+            image::RgbImage::from_raw(width, height, add_blue(&data.pixels)).map(Into::into)
+        }
+        Format::R8G8B8 => {
+            image::RgbImage::from_raw(width, height, data.pixels.clone()).map(Into::into)
+        }
+        Format::R8G8B8A8 => {
+            image::RgbaImage::from_raw(width, height, data.pixels.clone()).map(Into::into)
+        }
+        Format::R16 => image::ImageBuffer::<image::Luma<u16>, _>::from_raw(width, height, words())
+            .map(Into::into),
+        Format::R16G16 => {
+            image::ImageBuffer::<image::Rgb<u16>, _>::from_raw(width, height, add_blue(&words()))
+                .map(Into::into)
+        }
+        Format::R16G16B16 => {
+            image::ImageBuffer::<image::Rgb<u16>, _>::from_raw(width, height, words())
+                .map(Into::into)
+        }
+        Format::R16G16B16A16 => {
+            image::ImageBuffer::<image::Rgba<u16>, _>::from_raw(width, height, words())
+                .map(Into::into)
+        }
+        Format::R32G32B32FLOAT => {
+            image::Rgb32FImage::from_raw(width, height, floats()).map(Into::into)
+        }
+        Format::R32G32B32A32FLOAT => {
+            image::Rgba32FImage::from_raw(width, height, floats()).map(Into::into)
+        }
+    };
+    image.context("glTF image data doesn't match its size.")
+}
+
+// Red and green channel pairs to RGB
+fn add_blue<T: Copy + Default>(values: &[T]) -> Vec<T> {
+    values
+        .chunks_exact(2)
+        .flat_map(|rg| [rg[0], rg[1], T::default()])
+        .collect()
+}
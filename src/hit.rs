@@ -3,20 +3,60 @@ use std::{fmt::Debug, sync::Arc};
 use glam::{Vec2, Vec3};
 use rand::RngCore;
 
-use crate::{aabb::Aabb, interval::Interval, material::Material, ray::Ray};
+use crate::{
+    aabb::Aabb,
+    interval::Interval,
+    material::Material,
+    ray::{Ray, RayDifferential},
+};
 
 #[derive(Clone)]
 pub struct HitRecord {
     pub point: Vec3,
-    pub normal: Vec3,
+    pub normal: Vec3,           // shading normal, facing against the ray
+    pub geometric_normal: Vec3, // true surface normal, on the same side as normal
     pub material: Arc<dyn Material>,
     pub t: f32,
     pub uv: Vec2,
+    pub dpdu: Vec3, // surface derivatives along the texture coordinates, zero if unknown
+    pub dpdv: Vec3,
+    pub dpdx: Vec3, // screen space footprint of the hit, zero without ray differentials
+    pub dpdy: Vec3,
+    pub duvdx: Vec2,
+    pub duvdy: Vec2,
+    pub vertex_color: Option<Vec3>, // interpolated at the hit for meshes with vertex colors
     pub front_face: bool,
 }
 
 impl HitRecord {
-    // TODO: replace with constructor
+    pub fn new(
+        ray: Ray,
+        point: Vec3,
+        outward_normal: Vec3,
+        material: Arc<dyn Material>,
+        t: f32,
+        uv: Vec2,
+    ) -> Self {
+        let mut hit_record = Self {
+            point,
+            normal: outward_normal,
+            geometric_normal: outward_normal,
+            material,
+            t,
+            uv,
+            dpdu: Vec3::ZERO,
+            dpdv: Vec3::ZERO,
+            dpdx: Vec3::ZERO,
+            dpdy: Vec3::ZERO,
+            duvdx: Vec2::ZERO,
+            duvdy: Vec2::ZERO,
+            vertex_color: None,
+            front_face: true,
+        };
+        hit_record.set_face_normal(ray, outward_normal);
+        hit_record
+    }
+
     #[inline]
     pub fn set_face_normal(&mut self, ray: Ray, outward_normal: Vec3) {
         self.front_face = ray.direction.dot(outward_normal) < 0.0;
@@ -24,7 +64,134 @@ impl HitRecord {
             outward_normal
         } else {
             -outward_normal
+        };
+        self.geometric_normal = self.normal;
+    }
+
+    // Replace the shading normal with an interpolated or perturbed one, keeping the geometric
+    // normal and the face side
+    #[inline]
+    pub fn set_shading_normal(&mut self, outward_normal: Vec3) {
+        self.normal = if self.front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+    }
+
+    // Whether direction leaves on the same side of the surface for both normals
+    // Following an inconsistent direction would leak light through the surface
+    #[inline]
+    pub fn is_consistent(&self, direction: Vec3) -> bool {
+        self.normal.dot(direction) * self.geometric_normal.dot(direction) > 0.0
+    }
+
+    // The shading normal turned towards wo, and that one turned to the outside of the surface,
+    // for BSDFs that refract
+    #[inline]
+    pub fn facing_normals(&self, wo: Vec3) -> (Vec3, Vec3) {
+        let facing_normal = if self.normal.dot(wo) < 0.0 {
+            -self.normal
+        } else {
+            self.normal
+        };
+        let outward_normal = if self.front_face {
+            facing_normal
+        } else {
+            -facing_normal
+        };
+        (facing_normal, outward_normal)
+    }
+
+    // Fill in dpdx, dpdy, duvdx and duvdy by intersecting the offset rays with the tangent plane
+    // Leaves them at zero if the ray has no differentials
+    pub fn compute_differentials(&mut self, ray: Ray) {
+        let Some(differential) = ray.differential else {
+            return;
+        };
+
+        let n = self.geometric_normal;
+        let plane_offset = n.dot(self.point);
+        let plane_hit = |origin: Vec3, direction: Vec3| {
+            let t = (plane_offset - n.dot(origin)) / n.dot(direction);
+            t.is_finite().then(|| origin + t * direction)
+        };
+        let (Some(px), Some(py)) = (
+            plane_hit(differential.rx_origin, differential.rx_direction),
+            plane_hit(differential.ry_origin, differential.ry_direction),
+        ) else {
+            return;
+        };
+        self.dpdx = px - self.point;
+        self.dpdy = py - self.point;
+
+        // least squares solution of dp = dpdu * du + dpdv * dv
+        let ata00 = self.dpdu.dot(self.dpdu);
+        let ata01 = self.dpdu.dot(self.dpdv);
+        let ata11 = self.dpdv.dot(self.dpdv);
+        let inv_det = 1.0 / (ata00 * ata11 - ata01 * ata01);
+        if !inv_det.is_finite() {
+            return;
         }
+        let solve = |dp: Vec3| {
+            let atb0 = self.dpdu.dot(dp);
+            let atb1 = self.dpdv.dot(dp);
+            let duv = Vec2::new(
+                (ata11 * atb0 - ata01 * atb1) * inv_det,
+                (ata00 * atb1 - ata01 * atb0) * inv_det,
+            );
+            duv.clamp(Vec2::splat(-1e8), Vec2::splat(1e8))
+        };
+        self.duvdx = solve(self.dpdx);
+        self.duvdy = solve(self.dpdy);
+    }
+
+    // Differentials for a perfect mirror bounce in direction
+    // The surface is treated as locally flat, so curvature doesn't widen the footprint
+    pub fn reflected_differential(&self, ray_in: Ray, direction: Vec3) -> Option<RayDifferential> {
+        let differential = ray_in.differential?;
+        let n = self.normal;
+        let wo = -ray_in.direction.normalize();
+        let wi = direction.normalize();
+
+        let reflect = |rd: Vec3| {
+            let dwo = -rd.normalize() - wo;
+            wi - dwo + 2.0 * dwo.dot(n) * n
+        };
+
+        Some(RayDifferential {
+            rx_origin: self.point + self.dpdx,
+            rx_direction: reflect(differential.rx_direction),
+            ry_origin: self.point + self.dpdy,
+            ry_direction: reflect(differential.ry_direction),
+        })
+    }
+
+    // Differentials for a refraction in direction, eta is the incident over transmitted index
+    pub fn refracted_differential(
+        &self,
+        ray_in: Ray,
+        direction: Vec3,
+        eta: f32,
+    ) -> Option<RayDifferential> {
+        let differential = ray_in.differential?;
+        let n = self.normal;
+        let wo = -ray_in.direction.normalize();
+        let wi = direction.normalize();
+        let cos_o = wo.dot(n);
+        let cos_i = wi.dot(n).abs();
+
+        let refract = |rd: Vec3| {
+            let dwo = -rd.normalize() - wo;
+            let dmu = (eta - eta * eta * cos_o / cos_i) * dwo.dot(n);
+            wi - eta * dwo + dmu * n
+        };
+        Some(RayDifferential {
+            rx_origin: self.point + self.dpdx,
+            rx_direction: refract(differential.rx_direction),
+            ry_origin: self.point + self.dpdy,
+            ry_direction: refract(differential.ry_direction),
+        })
     }
 }
 
@@ -58,3 +225,30 @@ impl Hittable for EmptyHittable {
         Vec3::X
     }
 }
+
+// Solid angle density of direction from origin for a surface sampled uniformly over its area,
+// summed over every crossing since closed surfaces can be sampled on the far side too
+pub fn uniform_area_pdf_value(
+    object: &(impl Hittable + ?Sized),
+    area: f32,
+    origin: Vec3,
+    direction: Vec3,
+    rng: &mut dyn RngCore,
+) -> f32 {
+    const MAX_CROSSINGS: usize = 8;
+
+    let ray = Ray::new(origin, direction);
+    let mut t_min = 0.001;
+    let mut pdf = 0.0;
+    for _ in 0..MAX_CROSSINGS {
+        let Some(hit_record) = object.hit(ray, Interval::new(t_min, f32::INFINITY), rng) else {
+            break;
+        };
+
+        let distance_squared = hit_record.t * hit_record.t * direction.length_squared();
+        let cosine = (direction.dot(hit_record.geometric_normal) / direction.length()).abs();
+        pdf += distance_squared / (cosine * area);
+        t_min = hit_record.t + 0.001;
+    }
+    pdf
+}
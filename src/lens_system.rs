@@ -0,0 +1,446 @@
+use std::{fmt::Debug, path::Path};
+
+use anyhow::Context;
+use glam::{Vec2, Vec3};
+
+use crate::projection::Projection;
+
+const EXIT_PUPIL_BINS: usize = 64;
+const EXIT_PUPIL_GRID: usize = 128;
+
+// One interface of a lens prescription, front (scene side) first, all lengths in mm
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LensElement {
+    pub curvature_radius: f32, // positive with the center towards the film, 0 for flat
+    pub thickness: f32,        // along the axis to the next interface, or to the film
+    pub ior: f32,              // of the medium behind the interface, towards the film
+    pub aperture_radius: f32,
+}
+
+// Rays from the film through a sequence of spherical lens elements, as pbrt's realistic camera
+// Lens space has the film at z = 0 and the lenses towards -z, matching camera space
+#[derive(Clone, Debug)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    film_diagonal: f32, // mm
+    units_per_meter: f32,
+    exit_pupil_bounds: Vec<(Vec2, Vec2)>, // on the rear element plane, per film radius bin
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>, film_diagonal: f32) -> Self {
+        assert!(
+            !elements.is_empty(),
+            "A lens system needs at least one element."
+        );
+
+        let mut lens_system = Self {
+            elements,
+            film_diagonal,
+            units_per_meter: 1.0,
+            exit_pupil_bounds: Vec::new(),
+        };
+        lens_system.compute_exit_pupil_bounds();
+        lens_system
+    }
+
+    // Reads a prescription table with a row of radius, thickness, ior and aperture diameter per
+    // interface, in mm, as in pbrt's lens files. An ior of 0 is air
+    pub fn load(path: impl AsRef<Path>, film_diagonal: f32) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let table = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lens file {path:?}."))?;
+        let elements = parse_lens_table(&table)
+            .with_context(|| format!("Failed to parse lens file {path:?}."))?;
+        Ok(Self::new(elements, film_diagonal))
+    }
+
+    pub fn with_units_per_meter(mut self, units_per_meter: f32) -> Self {
+        self.units_per_meter = units_per_meter;
+        self
+    }
+
+    // Opens or stops down the flat interfaces between air, which are the aperture stop
+    pub fn with_aperture_stop(mut self, diameter: f32) -> Self {
+        for index in 0..self.elements.len() {
+            if self.elements[index].curvature_radius == 0.0 && self.is_air_gap(index) {
+                self.elements[index].aperture_radius = diameter / 2.0;
+            }
+        }
+        self.compute_exit_pupil_bounds();
+        self
+    }
+
+    // Moves the lens group along the axis to focus at focus_distance scene units from the film,
+    // using the thick lens approximation of the system
+    pub fn focused(mut self, focus_distance: f32) -> anyhow::Result<Self> {
+        let distance = focus_distance / self.units_per_meter * 1000.0;
+        let (focal_z, principal_z) = self
+            .cardinal_points()
+            .context("Lens system does not focus paraxial rays.")?;
+        let focal_length = focal_z[1] - principal_z[1];
+
+        // s^2 - (a + b) s + a b + f (a - b) = 0 for the shift s towards the scene, with the
+        // object distance a and the image principal plane b before the move
+        let a = principal_z[0] + distance;
+        let b = principal_z[1];
+        let discriminant = (a - b) * (a - b - 4.0 * focal_length);
+        if discriminant < 0.0 {
+            anyhow::bail!("Lens system cannot focus at {focus_distance}.");
+        }
+        let shift = 0.5 * (a + b - discriminant.sqrt());
+
+        let rear = self.elements.last_mut().unwrap();
+        rear.thickness += shift;
+        if rear.thickness <= 0.0 {
+            anyhow::bail!("Lens system cannot focus at {focus_distance}.");
+        }
+
+        self.compute_exit_pupil_bounds();
+        Ok(self)
+    }
+
+    pub fn elements(&self) -> &[LensElement] {
+        &self.elements
+    }
+
+    // Distance from the rear element to the film
+    pub fn back_focus(&self) -> f32 {
+        self.elements.last().unwrap().thickness
+    }
+
+    // air on both sides of interface index
+    fn is_air_gap(&self, index: usize) -> bool {
+        self.front_ior(index) == 1.0 && self.elements[index].ior == 1.0
+    }
+
+    fn front_ior(&self, index: usize) -> f32 {
+        if index > 0 {
+            self.elements[index - 1].ior
+        } else {
+            1.0
+        }
+    }
+
+    fn rear_z(&self) -> f32 {
+        -self.back_focus()
+    }
+
+    fn front_z(&self) -> f32 {
+        -self
+            .elements
+            .iter()
+            .map(|element| element.thickness)
+            .sum::<f32>()
+    }
+
+    // Ray through every interface from the film side, None when blocked or totally reflected
+    fn trace_from_film(&self, mut origin: Vec3, mut direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut element_z = 0.0;
+        for (index, element) in self.elements.iter().enumerate().rev() {
+            element_z -= element.thickness;
+            (origin, direction) = self.cross_interface(
+                element,
+                element_z,
+                origin,
+                direction,
+                element.ior,
+                self.front_ior(index),
+            )?;
+        }
+        Some((origin, direction))
+    }
+
+    // Ray through every interface from the scene side
+    fn trace_from_scene(&self, mut origin: Vec3, mut direction: Vec3) -> Option<(Vec3, Vec3)> {
+        let mut element_z = self.front_z();
+        for (index, element) in self.elements.iter().enumerate() {
+            (origin, direction) = self.cross_interface(
+                element,
+                element_z,
+                origin,
+                direction,
+                self.front_ior(index),
+                element.ior,
+            )?;
+            element_z += element.thickness;
+        }
+        Some((origin, direction))
+    }
+
+    fn cross_interface(
+        &self,
+        element: &LensElement,
+        element_z: f32,
+        origin: Vec3,
+        direction: Vec3,
+        ior_in: f32,
+        ior_out: f32,
+    ) -> Option<(Vec3, Vec3)> {
+        let (t, normal) = if element.curvature_radius == 0.0 {
+            ((element_z - origin.z) / direction.z, Vec3::Z)
+        } else {
+            let radius = element.curvature_radius;
+            let center = Vec3::new(0.0, 0.0, element_z + radius);
+            let origin_center = origin - center;
+            let a = direction.length_squared();
+            let h = -direction.dot(origin_center);
+            let c = origin_center.length_squared() - radius * radius;
+            let discriminant = h * h - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+
+            // the interface is the sphere's side at element_z, nearer for rays towards the center
+            let use_closer = (direction.z > 0.0) ^ (radius < 0.0);
+            let t = if use_closer {
+                (h - discriminant.sqrt()) / a
+            } else {
+                (h + discriminant.sqrt()) / a
+            };
+            (t, (origin + t * direction - center) / radius)
+        };
+        if t.is_nan() || t < 0.0 {
+            return None;
+        }
+
+        let point = origin + t * direction;
+        if point.x * point.x + point.y * point.y > element.aperture_radius * element.aperture_radius
+        {
+            return None;
+        }
+
+        if ior_in == ior_out {
+            return Some((point, direction));
+        }
+
+        // Snell's law with the normal facing the incoming side
+        let unit_direction = direction.normalize();
+        let normal = if normal.dot(unit_direction) > 0.0 {
+            -normal
+        } else {
+            normal
+        };
+        let eta = ior_in / ior_out;
+        let cos_theta = -unit_direction.dot(normal);
+        let sin2_theta_t = eta * eta * (1.0 - cos_theta * cos_theta);
+        if sin2_theta_t >= 1.0 {
+            return None;
+        }
+        let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+        let refracted = eta * unit_direction + (eta * cos_theta - cos_theta_t) * normal;
+
+        Some((point, refracted))
+    }
+
+    // z of the focal points and principal planes, scene side first
+    fn cardinal_points(&self) -> Option<([f32; 2], [f32; 2])> {
+        let height = 0.001 * self.film_diagonal;
+
+        let scene_origin = Vec3::new(height, 0.0, self.front_z() - 1.0);
+        let (scene_exit_origin, scene_exit_direction) =
+            self.trace_from_scene(scene_origin, Vec3::Z)?;
+        let film_origin = Vec3::new(height, 0.0, 1.0);
+        let (film_exit_origin, film_exit_direction) =
+            self.trace_from_film(film_origin, Vec3::NEG_Z)?;
+
+        // where the exiting ray crosses the axis, and where it is back at the entering height
+        let cardinal = |origin: Vec3, direction: Vec3| {
+            let focal_t = -origin.x / direction.x;
+            let principal_t = (height - origin.x) / direction.x;
+            (
+                origin.z + focal_t * direction.z,
+                origin.z + principal_t * direction.z,
+            )
+        };
+        let (image_focal, image_principal) = cardinal(scene_exit_origin, scene_exit_direction);
+        let (object_focal, object_principal) = cardinal(film_exit_origin, film_exit_direction);
+
+        let points = (
+            [object_focal, image_focal],
+            [object_principal, image_principal],
+        );
+        (points.0.iter().chain(&points.1).all(|z| z.is_finite())).then_some(points)
+    }
+
+    // Bounds of the points on the rear element plane whose rays from the film at a distance
+    // along +x get through the system, so lens samples land in the pupil
+    fn compute_exit_pupil_bounds(&mut self) {
+        let rear_z = self.rear_z();
+        let rear_radius = self.elements.last().unwrap().aperture_radius;
+        let grid_extent = 1.5 * rear_radius;
+        let cell_size = 2.0 * grid_extent / EXIT_PUPIL_GRID as f32;
+
+        self.exit_pupil_bounds = (0..EXIT_PUPIL_BINS)
+            .map(|bin| {
+                let film_radius =
+                    (bin as f32 + 0.5) / EXIT_PUPIL_BINS as f32 * self.film_diagonal / 2.0;
+                let film_point = Vec3::new(film_radius, 0.0, 0.0);
+
+                let mut bounds = (Vec2::INFINITY, Vec2::NEG_INFINITY);
+                for y in 0..EXIT_PUPIL_GRID {
+                    for x in 0..EXIT_PUPIL_GRID {
+                        let rear_point = Vec2::new(
+                            -grid_extent + (x as f32 + 0.5) * cell_size,
+                            -grid_extent + (y as f32 + 0.5) * cell_size,
+                        );
+                        let direction = rear_point.extend(rear_z) - film_point;
+                        if self.trace_from_film(film_point, direction).is_some() {
+                            bounds.0 = bounds.0.min(rear_point);
+                            bounds.1 = bounds.1.max(rear_point);
+                        }
+                    }
+                }
+
+                if bounds.0.x > bounds.1.x {
+                    // nothing gets through, keep sampling the whole rear element
+                    (Vec2::splat(-rear_radius), Vec2::splat(rear_radius))
+                } else {
+                    (bounds.0 - cell_size, bounds.1 + cell_size)
+                }
+            })
+            .collect();
+    }
+}
+
+impl Projection for LensSystem {
+    fn generate_ray(&self, film: Vec2, lens: Vec2, aspect_ratio: f32) -> Option<(Vec3, Vec3, f32)> {
+        // the lens flips the image, so the top right pixel sees through the bottom left film
+        let film_height = self.film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let film_size = Vec2::new(film_height * aspect_ratio, film_height);
+        let film_point = Vec2::new(0.5 - film.x, film.y - 0.5) * film_size;
+
+        let film_radius = film_point.length();
+        let bin = ((film_radius / (self.film_diagonal / 2.0) * EXIT_PUPIL_BINS as f32) as usize)
+            .min(EXIT_PUPIL_BINS - 1);
+        let (min, max) = self.exit_pupil_bounds[bin];
+        let (center_min, center_max) = self.exit_pupil_bounds[0];
+
+        // the unit disk sample over the ellipse around the bounds, rotated to the film point
+        let pupil_point = 0.5 * (min + max) + lens * (max - min) * std::f32::consts::FRAC_1_SQRT_2;
+        let rotation = if film_radius > 0.0 {
+            film_point / film_radius
+        } else {
+            Vec2::X
+        };
+        let rear_point = rotation.rotate(pupil_point).extend(self.rear_z());
+
+        let film_origin = film_point.extend(0.0);
+        let film_direction = rear_point - film_origin;
+        let (origin, direction) = self.trace_from_film(film_origin, film_direction)?;
+
+        // irradiance on the film from the sampled pupil area, falling off with cos^4 towards
+        // the edges, as pbrt weights its realistic camera rays. Relative to the pupil at the
+        // image center, so the exposure stays that of the camera settings
+        let cos_theta = film_direction.normalize().z.abs();
+        let weight = cos_theta.powi(4) * pupil_area(min, max) / pupil_area(center_min, center_max);
+
+        Some((origin * 0.001 * self.units_per_meter, direction, weight))
+    }
+}
+
+// Of the ellipse that lens samples cover around the bounds
+fn pupil_area(min: Vec2, max: Vec2) -> f32 {
+    let size = max - min;
+    std::f32::consts::PI * size.x * size.y / 2.0
+}
+
+pub fn parse_lens_table(table: &str) -> anyhow::Result<Vec<LensElement>> {
+    let mut elements = Vec::new();
+    for (line_index, line) in table.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let values = line
+            .split_whitespace()
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid number on line {}.", line_index + 1))?;
+        let [curvature_radius, thickness, ior, aperture_diameter] = values[..] else {
+            anyhow::bail!(
+                "Expected radius, thickness, ior and aperture on line {}.",
+                line_index + 1
+            );
+        };
+
+        elements.push(LensElement {
+            curvature_radius,
+            thickness,
+            ior: if ior == 0.0 { 1.0 } else { ior },
+            aperture_radius: aperture_diameter / 2.0,
+        });
+    }
+
+    if elements.is_empty() {
+        anyhow::bail!("Lens table has no elements.");
+    }
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // plano-convex singlet, f = 25.8 / (1.5 - 1) = 51.6 mm, focused near infinity
+    fn singlet() -> LensSystem {
+        let elements = vec![
+            LensElement {
+                curvature_radius: 25.8,
+                thickness: 5.0,
+                ior: 1.5,
+                aperture_radius: 12.5,
+            },
+            LensElement {
+                curvature_radius: 0.0,
+                thickness: 48.0,
+                ior: 1.0,
+                aperture_radius: 12.5,
+            },
+        ];
+        LensSystem::new(elements, 43.0)
+    }
+
+    #[test]
+    fn trace_focuses_parallel_rays_on_the_film() {
+        let lens_system = singlet();
+        let start_z = lens_system.front_z() - 10.0;
+
+        for height in [1.0, 3.0] {
+            let (origin, direction) = lens_system
+                .trace_from_scene(Vec3::new(height, 0.0, start_z), Vec3::Z)
+                .unwrap();
+            let axis_z = origin.z - origin.x / direction.x * direction.z;
+            assert!(axis_z.abs() < 0.5, "crosses the axis at {axis_z}");
+        }
+
+        // outside the aperture
+        assert!(
+            lens_system
+                .trace_from_scene(Vec3::new(13.0, 0.0, start_z), Vec3::Z)
+                .is_none()
+        );
+        assert!(
+            lens_system
+                .trace_from_film(Vec3::ZERO, Vec3::new(20.0, 0.0, -48.0))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn weight_is_one_at_the_image_center() {
+        let lens_system = singlet();
+
+        let (_, direction, center_weight) = lens_system
+            .generate_ray(Vec2::splat(0.5), Vec2::ZERO, 1.5)
+            .unwrap();
+        assert!(direction.normalize().abs_diff_eq(Vec3::NEG_Z, 1e-2));
+        assert!((center_weight - 1.0).abs() < 1e-2, "{center_weight}");
+
+        let (_, _, corner_weight) = lens_system
+            .generate_ray(Vec2::ZERO, Vec2::ZERO, 1.5)
+            .unwrap();
+        assert!(corner_weight < 0.8, "{corner_weight}");
+    }
+}
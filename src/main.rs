@@ -1,31 +1,54 @@
 mod aabb;
+mod animation;
+mod aperture;
+mod axis_aligned_box;
 mod bvh;
 mod camera;
 mod color;
+mod cone;
 mod constant_medium;
+mod cylinder;
+mod disk;
+mod gltf_loader;
 mod hit;
 mod hittable_list;
 mod interval;
+mod lens_system;
 mod material;
 mod mesh;
+mod microfacet;
+mod noise;
 mod onb;
 mod pdf;
+mod physical_camera;
+mod plane;
+mod ply;
+mod procedural_texture;
+mod projection;
 mod quad;
 mod ray;
+mod sdf;
 mod sphere;
+mod stereo;
+mod stl;
 mod texture;
+mod texture_graph;
+mod torus;
 mod transform;
 mod triangle;
+mod triangle_mesh;
 mod util;
 
-use std::sync::Arc;
+use std::{f32::consts::TAU, sync::Arc};
 
 use anyhow::Context;
-use glam::{Mat4, Quat, Vec2, vec3};
+use glam::{Quat, Vec2, vec3};
 
 use crate::{
+    animation::{Frame, Interpolation, SequenceOptions, Timeline, TransformTrack, render_sequence},
     bvh::BvhNode,
     camera::Camera,
+    hit::Hittable,
     hittable_list::HittableList,
     material::{DielectricMaterial, DiffuseLightMaterial, LambertianMaterial},
     mesh::load_obj_meshes,
@@ -36,6 +59,67 @@ use crate::{
 };
 
 fn main() -> anyhow::Result<()> {
+    let sequence_options = SequenceOptions::from_args(std::env::args().skip(1))?;
+
+    let white_material = Arc::new(LambertianMaterial::new(Arc::new(SolidColor::from_rgb(
+        0.73, 0.73, 0.73,
+    ))));
+
+    let cube_meshes = load_obj_meshes("resources/cube.obj", white_material)?;
+    let cube_mesh = cube_meshes
+        .into_iter()
+        .next()
+        .context("Missing cube mesh from obj file.")?;
+
+    let cube_mesh_bvh: Arc<dyn Hittable> = Arc::new(BvhNode::from_hittable_list(cube_mesh, -1));
+
+    // the tall box turns around once over the sequence, in thirds since rotations interpolate
+    // along the shortest arc
+    let timeline = Timeline::new(24.0, 0, 95);
+    let turn_duration = timeline.frame_time(timeline.last_frame + 1);
+    let start_rotation = Quat::from_euler(glam::EulerRot::XYZ, 0.0, 15.0f32.to_radians(), 0.0);
+    let mut cube_track = TransformTrack::new(
+        vec3(207.5, 165.0, -377.5),
+        start_rotation,
+        vec3(165.0, 330.0, 165.0),
+    );
+    for third in 1..=3 {
+        cube_track.rotation.add_key(
+            turn_duration * third as f32 / 3.0,
+            Quat::from_rotation_y(third as f32 * TAU / 3.0) * start_rotation,
+            Interpolation::Linear,
+        );
+    }
+
+    let Some(sequence_options) = sequence_options else {
+        let cube = Arc::new(Transform::new(cube_mesh_bvh, &cube_track.matrix_at(0.0)));
+        let Frame {
+            world,
+            lights,
+            camera,
+        } = cornell_box(cube, (0.0, 0.0));
+
+        let bvh = BvhNode::from_hittable_list(world, -1);
+        let mut imgbuf = image::RgbImage::new(camera.image_width(), camera.image_height());
+
+        // rayon::ThreadPoolBuilder::new().num_threads(10).build_global()?;
+
+        camera.render_threaded(&bvh, Arc::new(lights), &mut imgbuf);
+
+        imgbuf.save("output.png")?;
+
+        return Ok(());
+    };
+
+    render_sequence(&timeline, &sequence_options, |frame| {
+        let shutter = timeline.shutter_interval(frame);
+        let cube = Arc::new(cube_track.animated_transform(cube_mesh_bvh.clone(), shutter));
+        Ok(cornell_box(cube, shutter))
+    })
+}
+
+// The Cornell box with a glass ball and the given tall box
+fn cornell_box(cube: Arc<dyn Hittable>, shutter: (f32, f32)) -> Frame {
     let mut world = HittableList::new();
 
     let red_material = Arc::new(LambertianMaterial::new(Arc::new(SolidColor::from_rgb(
@@ -102,28 +186,12 @@ fn main() -> anyhow::Result<()> {
     ));
     world.add(light.clone());
 
-    let cube_meshes = load_obj_meshes("resources/cube.obj", white_material.clone())?;
-    let cube_mesh = cube_meshes
-        .into_iter()
-        .next()
-        .context("Missing cube mesh from obj file.")?;
-
-    let cube_mesh_bvh = Arc::new(BvhNode::from_hittable_list(cube_mesh, -1));
-    world.add(Arc::new(Transform::new(
-        cube_mesh_bvh.clone(),
-        &Mat4::from_scale_rotation_translation(
-            vec3(165.0, 330.0, 165.0),
-            Quat::from_euler(glam::EulerRot::XYZ, 0.0, 15.0f32.to_radians(), 0.0),
-            vec3(207.5, 165.0, -377.5),
-        ),
-    )));
+    world.add(cube);
 
     let glass_material = Arc::new(DielectricMaterial::new(1.5));
     let glass_ball = Arc::new(Sphere::new(vec3(342.5, 82.5, -147.5), 90.0, glass_material));
     world.add(glass_ball.clone());
 
-    let bvh = BvhNode::from_hittable_list(world, -1);
-
     let mut lights = HittableList::new();
     lights.add(light);
     lights.add(glass_ball);
@@ -143,15 +211,12 @@ fn main() -> anyhow::Result<()> {
         500,
         10,
         vec3(0.0, 0.0, 0.0),
-    );
-
-    let mut imgbuf = image::RgbImage::new(image_width as u32, image_height as u32);
-
-    // rayon::ThreadPoolBuilder::new().num_threads(10).build_global()?;
-
-    camera.render_threaded(&bvh, Arc::new(lights), &mut imgbuf);
-
-    imgbuf.save("output.png")?;
-
-    Ok(())
+    )
+    .with_shutter(shutter.0, shutter.1);
+
+    Frame {
+        world,
+        lights,
+        camera,
+    }
 }
@@ -6,9 +6,14 @@ use rand::{Rng, RngCore};
 
 use crate::{
     hit::HitRecord,
-    pdf::{CosinePdf, Pdf, SpherePdf},
+    microfacet::{
+        GgxDistribution, PrincipledBsdf, PrincipledParameters, RoughDielectricBsdf,
+        fresnel_dielectric,
+    },
+    onb::Onb,
+    pdf::{CosinePdf, Pdf, PrincipledPdf, RoughDielectricPdf, SpherePdf},
     ray::Ray,
-    texture::{SolidColor, Texture},
+    texture::{SolidColor, Texture, TextureContext},
     util::random_unit_vec3,
 };
 
@@ -26,9 +31,21 @@ pub trait Material: Send + Sync + Debug {
         rng: &mut dyn RngCore,
     ) -> Option<ScatterRecord>;
 
-    fn emitted(&self, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3;
+    fn emitted(&self, ray_in: Ray, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3;
 
     fn scattering_pdf(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> f32;
+
+    // Colored version of scattering_pdf, for BSDFs that can't be split into a constant
+    // attenuation times a scalar
+    fn scattering_value(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> Vec3 {
+        Vec3::splat(self.scattering_pdf(ray_in, hit_record, scattered))
+    }
+
+    // Whether the surface is there at all at a hit, false lets the ray continue through it
+    // Called by the primitives while intersecting, so the hit has no ray differentials yet
+    fn alpha_test(&self, _hit_record: &HitRecord, _rng: &mut dyn RngCore) -> bool {
+        true
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -49,7 +66,7 @@ impl Material for LambertianMaterial {
         hit_record: &HitRecord,
         _rng: &mut dyn RngCore,
     ) -> Option<ScatterRecord> {
-        let attenuation = self.texture.value(hit_record.uv, hit_record.point);
+        let attenuation = self.texture.value(&TextureContext::from_hit(hit_record));
         let pdf = Arc::new(CosinePdf::new(hit_record.normal));
 
         Some(ScatterRecord {
@@ -58,7 +75,7 @@ impl Material for LambertianMaterial {
         })
     }
 
-    fn emitted(&self, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
+    fn emitted(&self, _ray_in: Ray, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
         Vec3::ZERO
     }
 
@@ -99,11 +116,16 @@ impl Material for MetalMaterial {
         hit_record: &HitRecord,
         rng: &mut dyn RngCore,
     ) -> Option<ScatterRecord> {
-        let attenuation = self.texture.value(hit_record.uv, hit_record.point);
+        let attenuation = self.texture.value(&TextureContext::from_hit(hit_record));
 
         let reflected = ray_in.direction.reflect(hit_record.normal).normalize();
         let reflected_fuzzed = reflected.normalize() + (self.fuzz * random_unit_vec3(rng));
-        let ray = Ray::new(hit_record.point, reflected_fuzzed);
+        let ray = Ray::with_differential(
+            hit_record.point,
+            reflected_fuzzed,
+            ray_in.time,
+            hit_record.reflected_differential(ray_in, reflected_fuzzed),
+        );
 
         Some(ScatterRecord {
             attenuation,
@@ -111,7 +133,7 @@ impl Material for MetalMaterial {
         })
     }
 
-    fn emitted(&self, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
+    fn emitted(&self, _ray_in: Ray, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
         Vec3::ZERO
     }
 
@@ -123,17 +145,27 @@ impl Material for MetalMaterial {
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct DielectricMaterial {
     pub refraction_index: f32,
+    pub absorption: Vec3, // per unit distance travelled inside
 }
 
 impl DielectricMaterial {
     pub const fn new(refraction_index: f32) -> Self {
-        Self { refraction_index }
+        Self::with_absorption(refraction_index, Vec3::ZERO)
+    }
+
+    pub const fn with_absorption(refraction_index: f32, absorption: Vec3) -> Self {
+        Self {
+            refraction_index,
+            absorption,
+        }
     }
 
-    fn dielectric_reflectance(cosine: f32, refraction_index: f32) -> f32 {
-        let mut r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
-        r0 *= r0;
-        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    // color is the fraction of light left after travelling distance through the material
+    pub fn with_transmittance(refraction_index: f32, color: Vec3, distance: f32) -> Self {
+        Self::with_absorption(
+            refraction_index,
+            absorption_from_transmittance(color, distance),
+        )
     }
 }
 
@@ -144,7 +176,7 @@ impl Material for DielectricMaterial {
         hit_record: &HitRecord,
         rng: &mut dyn RngCore,
     ) -> Option<ScatterRecord> {
-        let attenuation = Vec3::ONE;
+        let attenuation = beer_lambert(self.absorption, ray_in, hit_record);
 
         let ri = if hit_record.front_face {
             1.0 / self.refraction_index
@@ -154,26 +186,26 @@ impl Material for DielectricMaterial {
 
         let unit_direction = ray_in.direction.normalize();
         let cos_theta = (-unit_direction).dot(hit_record.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        let cannot_refract = ri * sin_theta > 1.0;
-
-        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > rng.random::<f32>()
-        {
-            unit_direction.reflect(hit_record.normal)
+        // fresnel_dielectric takes eta as transmitted over incident and is 1 under total internal
+        // reflection, so cannot_refract is covered
+        let ray = if fresnel_dielectric(cos_theta, 1.0 / ri) > rng.random::<f32>() {
+            let direction = unit_direction.reflect(hit_record.normal);
+            let differential = hit_record.reflected_differential(ray_in, direction);
+            Ray::with_differential(hit_record.point, direction, ray_in.time, differential)
         } else {
-            unit_direction.refract(hit_record.normal, ri)
+            let direction = unit_direction.refract(hit_record.normal, ri);
+            let differential = hit_record.refracted_differential(ray_in, direction, ri);
+            Ray::with_differential(hit_record.point, direction, ray_in.time, differential)
         };
 
-        let ray = Ray::new(hit_record.point, direction);
-
         Some(ScatterRecord {
             attenuation,
             pdf_or_skip_ray: Either::Right(ray),
         })
     }
 
-    fn emitted(&self, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
+    fn emitted(&self, _ray_in: Ray, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
         Vec3::ZERO
     }
 
@@ -182,11 +214,211 @@ impl Material for DielectricMaterial {
     }
 }
 
-impl DielectricMaterial {
-    fn reflectance(cosine: f32, refraction_index: f32) -> f32 {
-        let r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
-        let r0 = r0 * r0;
-        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+fn absorption_from_transmittance(color: Vec3, distance: f32) -> Vec3 {
+    let color = color.clamp(Vec3::splat(1e-6), Vec3::ONE);
+    -Vec3::new(color.x.ln(), color.y.ln(), color.z.ln()) / distance
+}
+
+// Attenuation for a ray that has just crossed the inside of a dielectric
+// A back face hit means the ray travelled hit_record.t inside the object
+fn beer_lambert(absorption: Vec3, ray_in: Ray, hit_record: &HitRecord) -> Vec3 {
+    if hit_record.front_face || absorption == Vec3::ZERO {
+        return Vec3::ONE;
+    }
+    let distance = hit_record.t * ray_in.direction.length();
+    (-absorption * distance).exp()
+}
+
+// Frosted glass, microfacet reflection and transmission
+// Falls back to DielectricMaterial when the roughness is close to zero
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RoughDielectricMaterial {
+    pub refraction_index: f32,
+    pub roughness: f32,
+    pub absorption: Vec3,
+}
+
+impl RoughDielectricMaterial {
+    pub const fn new(refraction_index: f32, roughness: f32) -> Self {
+        Self::with_absorption(refraction_index, roughness, Vec3::ZERO)
+    }
+
+    pub const fn with_absorption(refraction_index: f32, roughness: f32, absorption: Vec3) -> Self {
+        Self {
+            refraction_index,
+            roughness,
+            absorption,
+        }
+    }
+
+    pub fn with_transmittance(
+        refraction_index: f32,
+        roughness: f32,
+        color: Vec3,
+        distance: f32,
+    ) -> Self {
+        Self::with_absorption(
+            refraction_index,
+            roughness,
+            absorption_from_transmittance(color, distance),
+        )
+    }
+
+    fn distribution(&self) -> GgxDistribution {
+        GgxDistribution::from_roughness(self.roughness)
+    }
+
+    fn bsdf(&self, ray_in: Ray, hit_record: &HitRecord) -> RoughDielectricBsdf {
+        let wo = -ray_in.direction.normalize();
+        let (_, outward_normal) = hit_record.facing_normals(wo);
+
+        RoughDielectricBsdf::new(
+            outward_normal,
+            wo,
+            self.refraction_index,
+            self.distribution(),
+        )
+    }
+}
+
+impl Material for RoughDielectricMaterial {
+    fn scatter(
+        &self,
+        ray_in: Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        if self.distribution().is_smooth() {
+            return DielectricMaterial::with_absorption(self.refraction_index, self.absorption)
+                .scatter(ray_in, hit_record, rng);
+        }
+
+        let pdf = Arc::new(RoughDielectricPdf::new(self.bsdf(ray_in, hit_record)));
+
+        Some(ScatterRecord {
+            attenuation: beer_lambert(self.absorption, ray_in, hit_record),
+            pdf_or_skip_ray: Either::Left(pdf),
+        })
+    }
+
+    fn emitted(&self, _ray_in: Ray, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    fn scattering_pdf(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> f32 {
+        if self.distribution().is_smooth() {
+            return 0.0;
+        }
+        self.bsdf(ray_in, hit_record).eval(scattered.direction)
+    }
+}
+
+// Uber material driven by PBR parameters, see PrincipledBsdf
+// Scalar parameters read the luminance of their texture, through Texture::scalar_value
+#[derive(Clone, Debug)]
+pub struct PrincipledMaterial {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>, // 0.5 is a reflectance of 4%
+    pub transmission: Arc<dyn Texture>,
+    pub refraction_index: f32,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+    pub emission_strength: f32,
+}
+
+impl PrincipledMaterial {
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: Arc::new(SolidColor::splat(0.0)),
+            roughness: Arc::new(SolidColor::splat(0.5)),
+            specular: Arc::new(SolidColor::splat(0.5)),
+            transmission: Arc::new(SolidColor::splat(0.0)),
+            refraction_index: 1.5,
+            clearcoat: Arc::new(SolidColor::splat(0.0)),
+            clearcoat_roughness: Arc::new(SolidColor::splat(0.1)),
+            sheen: Arc::new(SolidColor::splat(0.0)),
+            sheen_tint: Arc::new(SolidColor::splat(0.5)),
+            emission: Arc::new(SolidColor::splat(0.0)),
+            emission_strength: 0.0,
+        }
+    }
+
+    fn parameters(&self, hit_record: &HitRecord) -> PrincipledParameters {
+        let context = TextureContext::from_hit(hit_record);
+        let scalar = |texture: &Arc<dyn Texture>| texture.scalar_value(&context);
+
+        PrincipledParameters {
+            base_color: self.base_color.value(&context),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            transmission: scalar(&self.transmission),
+            refraction_index: self.refraction_index,
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_roughness: scalar(&self.clearcoat_roughness),
+            sheen: scalar(&self.sheen),
+            sheen_tint: scalar(&self.sheen_tint),
+        }
+    }
+
+    fn bsdf(&self, ray_in: Ray, hit_record: &HitRecord) -> PrincipledBsdf {
+        let wo = -ray_in.direction.normalize();
+        let (facing_normal, outward_normal) = hit_record.facing_normals(wo);
+
+        PrincipledBsdf::new(
+            self.parameters(hit_record),
+            facing_normal,
+            outward_normal,
+            wo,
+        )
+    }
+}
+
+impl Material for PrincipledMaterial {
+    fn scatter(
+        &self,
+        ray_in: Ray,
+        hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let pdf = Arc::new(PrincipledPdf::new(self.bsdf(ray_in, hit_record)));
+
+        Some(ScatterRecord {
+            attenuation: Vec3::ONE,
+            pdf_or_skip_ray: Either::Left(pdf),
+        })
+    }
+
+    fn emitted(&self, _ray_in: Ray, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3 {
+        if self.emission_strength == 0.0 || !hit_record.front_face {
+            return Vec3::ZERO;
+        }
+        let context = TextureContext {
+            uv,
+            point,
+            ..TextureContext::from_hit(hit_record)
+        };
+        self.emission.value(&context) * self.emission_strength
+    }
+
+    fn scattering_pdf(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> f32 {
+        self.bsdf(ray_in, hit_record).pdf(scattered.direction)
+    }
+
+    fn scattering_value(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> Vec3 {
+        self.bsdf(ray_in, hit_record).eval(scattered.direction)
+    }
+}
+
+impl Default for PrincipledMaterial {
+    fn default() -> Self {
+        Self::new(Arc::new(SolidColor::splat(0.8)))
     }
 }
 
@@ -212,11 +444,71 @@ impl Material for DiffuseLightMaterial {
         None
     }
 
-    fn emitted(&self, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3 {
+    fn emitted(&self, _ray_in: Ray, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3 {
         if !hit_record.front_face {
             return Vec3::ZERO;
         }
-        self.texture.value(uv, point) * self.strength
+        let context = TextureContext {
+            uv,
+            point,
+            ..TextureContext::from_hit(hit_record)
+        };
+        self.texture.value(&context) * self.strength
+    }
+
+    fn scattering_pdf(&self, _ray_in: Ray, _hit_record: &HitRecord, _scattered: Ray) -> f32 {
+        0.0
+    }
+}
+
+// A diffuse light that only shines into a cone around direction, as KHR_lights_punctual spot
+// lights do, fading out between the inner and outer cone angles
+#[derive(Clone, Debug)]
+pub struct SpotLightMaterial {
+    pub light: DiffuseLightMaterial,
+    pub direction: Vec3,
+    pub inner_cone_angle: f32, // radians from direction
+    pub outer_cone_angle: f32,
+}
+
+impl SpotLightMaterial {
+    pub fn new(
+        light: DiffuseLightMaterial,
+        direction: Vec3,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        Self {
+            light,
+            direction: direction.normalize(),
+            inner_cone_angle,
+            outer_cone_angle,
+        }
+    }
+
+    // The falloff recommended by KHR_lights_punctual, squared from linear in the cosine
+    fn attenuation(&self, emitted_direction: Vec3) -> f32 {
+        let cos_outer = self.outer_cone_angle.cos();
+        let scale = 1.0 / (self.inner_cone_angle.cos() - cos_outer).max(0.001);
+        let attenuation =
+            ((self.direction.dot(emitted_direction) - cos_outer) * scale).clamp(0.0, 1.0);
+        attenuation * attenuation
+    }
+}
+
+impl Material for SpotLightMaterial {
+    fn scatter(
+        &self,
+        _ray_in: Ray,
+        _hit_record: &HitRecord,
+        _rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, ray_in: Ray, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3 {
+        self.attenuation(-ray_in.direction.normalize())
+            * self.light.emitted(ray_in, hit_record, uv, point)
     }
 
     fn scattering_pdf(&self, _ray_in: Ray, _hit_record: &HitRecord, _scattered: Ray) -> f32 {
@@ -242,7 +534,7 @@ impl Material for IsotropicMaterial {
         hit_record: &HitRecord,
         rng: &mut dyn RngCore,
     ) -> Option<ScatterRecord> {
-        let attenuation = self.texture.value(hit_record.uv, hit_record.point);
+        let attenuation = self.texture.value(&TextureContext::from_hit(hit_record));
         let pdf = Arc::new(SpherePdf);
 
         Some(ScatterRecord {
@@ -251,7 +543,7 @@ impl Material for IsotropicMaterial {
         })
     }
 
-    fn emitted(&self, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
+    fn emitted(&self, _ray_in: Ray, _hit_record: &HitRecord, _uv: Vec2, _point: Vec3) -> Vec3 {
         Vec3::ZERO
     }
 
@@ -259,3 +551,257 @@ impl Material for IsotropicMaterial {
         std::f32::consts::FRAC_1_PI * 0.25 // 1 / 4pi
     }
 }
+
+#[derive(Clone, Debug)]
+pub enum NormalMap {
+    // RGB encoded tangent space normals, strength scales the tangential part
+    TangentSpace {
+        texture: Arc<dyn Texture>,
+        strength: f32,
+    },
+    // Grayscale height map, scale is the displacement of a height of one
+    Bump {
+        texture: Arc<dyn Texture>,
+        scale: f32,
+    },
+}
+
+// Wraps any surface material and perturbs the shading normal before it scatters
+#[derive(Clone, Debug)]
+pub struct NormalMappedMaterial {
+    pub material: Arc<dyn Material>,
+    pub normal_map: NormalMap,
+}
+
+impl NormalMappedMaterial {
+    // uv offset for the bump map finite differences when the footprint is unknown
+    const BUMP_DELTA: f32 = 0.0005;
+
+    pub const fn new(material: Arc<dyn Material>, normal_map: NormalMap) -> Self {
+        Self {
+            material,
+            normal_map,
+        }
+    }
+
+    pub const fn with_normal_texture(
+        material: Arc<dyn Material>,
+        texture: Arc<dyn Texture>,
+        strength: f32,
+    ) -> Self {
+        Self::new(material, NormalMap::TangentSpace { texture, strength })
+    }
+
+    pub const fn with_bump_texture(
+        material: Arc<dyn Material>,
+        texture: Arc<dyn Texture>,
+        scale: f32,
+    ) -> Self {
+        Self::new(material, NormalMap::Bump { texture, scale })
+    }
+
+    fn perturbed(&self, hit_record: &HitRecord) -> HitRecord {
+        let outward_normal = if hit_record.front_face {
+            hit_record.normal
+        } else {
+            -hit_record.normal
+        };
+
+        let perturbed_normal = match &self.normal_map {
+            NormalMap::TangentSpace { texture, strength } => {
+                let (tangent, bitangent) = Self::tangent_frame(hit_record, outward_normal);
+                let encoded = texture.value(&TextureContext::from_hit(hit_record));
+                let normal = 2.0 * encoded - Vec3::ONE;
+                normal.x * strength * tangent
+                    + normal.y * strength * bitangent
+                    + normal.z * outward_normal
+            }
+            NormalMap::Bump { texture, scale } => {
+                let (dpdu, dpdv) = if hit_record.dpdu == Vec3::ZERO {
+                    Self::tangent_frame(hit_record, outward_normal)
+                } else {
+                    (hit_record.dpdu, hit_record.dpdv)
+                };
+
+                let context = TextureContext::from_hit(hit_record);
+                let height = |uv: Vec2, point: Vec3| {
+                    texture.scalar_value(&TextureContext {
+                        uv,
+                        point,
+                        ..context
+                    })
+                };
+
+                // finite differences about half a pixel wide, or a fixed step without
+                // ray differentials
+                let delta_or_default = |delta: f32| {
+                    if delta > 0.0 { delta } else { Self::BUMP_DELTA }
+                };
+                let du = delta_or_default(0.5 * (context.duvdx.x.abs() + context.duvdy.x.abs()));
+                let dv = delta_or_default(0.5 * (context.duvdx.y.abs() + context.duvdy.y.abs()));
+
+                let center = height(hit_record.uv, hit_record.point);
+                let u_displaced = height(
+                    hit_record.uv + Vec2::new(du, 0.0),
+                    hit_record.point + du * dpdu,
+                );
+                let v_displaced = height(
+                    hit_record.uv + Vec2::new(0.0, dv),
+                    hit_record.point + dv * dpdv,
+                );
+
+                let dpdu = dpdu + scale * (u_displaced - center) / du * outward_normal;
+                let dpdv = dpdv + scale * (v_displaced - center) / dv * outward_normal;
+                let normal = dpdu.cross(dpdv);
+                if normal.dot(outward_normal) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                }
+            }
+        };
+
+        let mut perturbed = hit_record.clone();
+        if let Some(perturbed_normal) = perturbed_normal.try_normalize() {
+            perturbed.set_shading_normal(perturbed_normal);
+        }
+        perturbed
+    }
+
+    // Orthonormal tangent and bitangent following dpdu and dpdv when the surface has them
+    fn tangent_frame(hit_record: &HitRecord, normal: Vec3) -> (Vec3, Vec3) {
+        let tangent = hit_record.dpdu - normal * normal.dot(hit_record.dpdu);
+        let Some(tangent) = tangent.try_normalize() else {
+            let uvw = Onb::new(normal);
+            return (uvw.u, uvw.v);
+        };
+
+        let bitangent = normal.cross(tangent);
+        if bitangent.dot(hit_record.dpdv) < 0.0 {
+            (tangent, -bitangent)
+        } else {
+            (tangent, bitangent)
+        }
+    }
+}
+
+impl Material for NormalMappedMaterial {
+    fn scatter(
+        &self,
+        ray_in: Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        let perturbed = self.perturbed(hit_record);
+        let scatter_record = self.material.scatter(ray_in, &perturbed, rng)?;
+
+        // a perturbed normal can send specular rays into the surface
+        if let Either::Right(ray) = scatter_record.pdf_or_skip_ray
+            && !perturbed.is_consistent(ray.direction)
+        {
+            return None;
+        }
+
+        Some(scatter_record)
+    }
+
+    fn emitted(&self, ray_in: Ray, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3 {
+        self.material.emitted(ray_in, hit_record, uv, point)
+    }
+
+    fn scattering_pdf(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> f32 {
+        let perturbed = self.perturbed(hit_record);
+        if !perturbed.is_consistent(scattered.direction) {
+            return 0.0;
+        }
+        self.material.scattering_pdf(ray_in, &perturbed, scattered)
+    }
+
+    fn scattering_value(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> Vec3 {
+        let perturbed = self.perturbed(hit_record);
+        if !perturbed.is_consistent(scattered.direction) {
+            return Vec3::ZERO;
+        }
+        self.material
+            .scattering_value(ray_in, &perturbed, scattered)
+    }
+
+    fn alpha_test(&self, hit_record: &HitRecord, rng: &mut dyn RngCore) -> bool {
+        self.material.alpha_test(hit_record, rng)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AlphaMode {
+    // Cut out where the opacity is below the threshold, for hard edged masks
+    Threshold(f32),
+    // Pass through with probability 1 - opacity, for soft edges and partial transparency
+    Stochastic,
+}
+
+impl Default for AlphaMode {
+    fn default() -> Self {
+        AlphaMode::Threshold(0.5)
+    }
+}
+
+// Wraps any surface material with an opacity mask, for leaves, fences and other cutouts
+#[derive(Clone, Debug)]
+pub struct AlphaMaskedMaterial {
+    pub material: Arc<dyn Material>,
+    pub opacity: Arc<dyn Texture>,
+    pub mode: AlphaMode,
+}
+
+impl AlphaMaskedMaterial {
+    pub fn new(material: Arc<dyn Material>, opacity: Arc<dyn Texture>) -> Self {
+        Self::with_mode(material, opacity, AlphaMode::default())
+    }
+
+    pub const fn with_mode(
+        material: Arc<dyn Material>,
+        opacity: Arc<dyn Texture>,
+        mode: AlphaMode,
+    ) -> Self {
+        Self {
+            material,
+            opacity,
+            mode,
+        }
+    }
+}
+
+impl Material for AlphaMaskedMaterial {
+    fn scatter(
+        &self,
+        ray_in: Ray,
+        hit_record: &HitRecord,
+        rng: &mut dyn RngCore,
+    ) -> Option<ScatterRecord> {
+        self.material.scatter(ray_in, hit_record, rng)
+    }
+
+    fn emitted(&self, ray_in: Ray, hit_record: &HitRecord, uv: Vec2, point: Vec3) -> Vec3 {
+        self.material.emitted(ray_in, hit_record, uv, point)
+    }
+
+    fn scattering_pdf(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> f32 {
+        self.material.scattering_pdf(ray_in, hit_record, scattered)
+    }
+
+    fn scattering_value(&self, ray_in: Ray, hit_record: &HitRecord, scattered: Ray) -> Vec3 {
+        self.material
+            .scattering_value(ray_in, hit_record, scattered)
+    }
+
+    fn alpha_test(&self, hit_record: &HitRecord, rng: &mut dyn RngCore) -> bool {
+        let opacity = self
+            .opacity
+            .scalar_value(&TextureContext::from_hit(hit_record));
+        let visible = match self.mode {
+            AlphaMode::Threshold(threshold) => opacity >= threshold,
+            AlphaMode::Stochastic => opacity >= 1.0 || rng.random::<f32>() < opacity,
+        };
+        visible && self.material.alpha_test(hit_record, rng)
+    }
+}
@@ -1,17 +1,37 @@
-use std::{fmt::Debug, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use glam::{Vec2, Vec3};
+use glam::{Vec2, Vec3, Vec4};
 
 use crate::{
     hittable_list::HittableList,
-    material::{LambertianMaterial, Material},
-    texture::{ImageTexture, SolidColor},
-    triangle::Triangle,
+    material::{
+        AlphaMaskedMaterial, LambertianMaterial, Material, NormalMap, NormalMappedMaterial,
+        PrincipledMaterial,
+    },
+    texture::{ImageTexture, SolidColor, Texture, TextureUsage},
+    triangle_mesh::TriangleMesh,
 };
 
+// Faces meeting at an angle below this are smooth shaded when a mesh has no normals
+pub const DEFAULT_SMOOTHING_ANGLE: f32 = 60.0;
+
 pub fn load_obj_meshes(
     path: impl AsRef<Path> + Debug,
     default_material: Arc<dyn Material>,
+) -> anyhow::Result<Vec<HittableList>> {
+    load_obj_meshes_with_smoothing(path, default_material, DEFAULT_SMOOTHING_ANGLE)
+}
+
+// smoothing_angle is in degrees and only used for meshes without normals, 0 for flat shading
+pub fn load_obj_meshes_with_smoothing(
+    path: impl AsRef<Path> + Debug,
+    default_material: Arc<dyn Material>,
+    smoothing_angle: f32,
 ) -> anyhow::Result<Vec<HittableList>> {
     let (models, materials) = tobj::load_obj(
         &path,
@@ -24,11 +44,16 @@ pub fn load_obj_meshes(
 
     let materials = materials?;
 
-    let mut out_meshes = Vec::with_capacity(models.len());
-
     let parent_path = path.as_ref().parent().expect("This should have a parent.");
+    let mut texture_cache = TextureCache::new(parent_path);
+    let materials = materials
+        .iter()
+        .map(|mtl_material| translate_mtl_material(mtl_material, &mut texture_cache))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut out_meshes = Vec::with_capacity(models.len());
 
-    for (index, model) in models.iter().enumerate() {
+    for model in &models {
         let mesh = &model.mesh;
 
         eprintln!(
@@ -38,59 +63,460 @@ pub fn load_obj_meshes(
             mesh.indices.len()
         );
 
-        let mut material = default_material.clone();
+        let material = match mesh.material_id {
+            Some(material_id) => materials[material_id].clone(),
+            None => default_material.clone(),
+        };
 
-        if let Some(material_id) = mesh.material_id {
-            let mtl_material = &materials[material_id];
-            if let Some(diffuse) = mtl_material.diffuse {
-                material = Arc::new(LambertianMaterial::new(Arc::new(SolidColor::new(
-                    Vec3::from(diffuse),
-                ))));
+        let indexed_mesh = IndexedMesh {
+            positions: mesh
+                .positions
+                .chunks_exact(3)
+                .map(Vec3::from_slice)
+                .collect(),
+            faces: mesh
+                .indices
+                .chunks_exact(3)
+                .map(|face| [face[0] as usize, face[1] as usize, face[2] as usize])
+                .collect(),
+            normals: (!mesh.normals.is_empty())
+                .then(|| mesh.normals.chunks_exact(3).map(Vec3::from_slice).collect()),
+            uvs: (!mesh.texcoords.is_empty()).then(|| {
+                mesh.texcoords
+                    .chunks_exact(2)
+                    .map(Vec2::from_slice)
+                    .collect()
+            }),
+            ..Default::default()
+        };
+
+        let mut list = HittableList::new();
+        list.add(Arc::new(triangle_mesh_from_faces(
+            indexed_mesh,
+            smoothing_angle,
+            vec![material],
+        )));
+        out_meshes.push(list);
+    }
+
+    Ok(out_meshes)
+}
+
+// Vertex buffers and faces read by a loader, on their way into a TriangleMesh
+// The optional buffers have one entry per position
+#[derive(Default, Debug)]
+pub struct IndexedMesh {
+    pub positions: Vec<Vec3>,
+    pub faces: Vec<[usize; 3]>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<Vec2>>,
+    pub tangents: Option<Vec<Vec4>>, // glTF style, see TriangleMesh::with_tangents
+    pub colors: Option<Vec<Vec3>>,   // linear
+    pub material_indices: Vec<u32>,  // per face into the materials, empty for a single one
+}
+
+// The TriangleMesh for a loaded mesh, shared by all the mesh loaders
+// Normals are per vertex, generated from smoothing_angle when missing, with vertices split
+// along the hard edges
+pub fn triangle_mesh_from_faces(
+    mesh: IndexedMesh,
+    smoothing_angle: f32,
+    materials: Vec<Arc<dyn Material>>,
+) -> TriangleMesh {
+    let IndexedMesh {
+        mut positions,
+        faces,
+        mut normals,
+        mut uvs,
+        mut tangents,
+        mut colors,
+        material_indices,
+    } = mesh;
+    let corner_normals = (normals.is_none() && smoothing_angle > 0.0)
+        .then(|| generate_smooth_normals(&positions, &faces, smoothing_angle));
+    let mut faces: Vec<[u32; 3]> = faces.iter().map(|face| face.map(|i| i as u32)).collect();
+
+    if let Some(corner_normals) = corner_normals {
+        // one vertex per distinct vertex and normal pair, remembering the vertex it came from
+        let mut sources = Vec::with_capacity(positions.len());
+        let mut split_normals = Vec::with_capacity(positions.len());
+        let mut split_indices: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+        for (face, face_normals) in faces.iter_mut().zip(&corner_normals) {
+            for (vertex, normal) in face.iter_mut().zip(face_normals) {
+                let key = (*vertex, normal.to_array().map(f32::to_bits));
+                *vertex = *split_indices.entry(key).or_insert_with(|| {
+                    sources.push(*vertex as usize);
+                    split_normals.push(*normal);
+                    sources.len() as u32 - 1
+                });
             }
-            if let Some(diffuse_texture) = &mtl_material.diffuse_texture {
-                material = Arc::new(LambertianMaterial::new(Arc::new(ImageTexture::load(
-                    parent_path.join(diffuse_texture),
-                )?)));
+        }
+
+        fn gather<T: Copy>(values: &[T], sources: &[usize]) -> Vec<T> {
+            sources.iter().map(|&source| values[source]).collect()
+        }
+        positions = gather(&positions, &sources);
+        normals = Some(split_normals);
+        uvs = uvs.map(|uvs| gather(&uvs, &sources));
+        tangents = tangents.map(|tangents| gather(&tangents, &sources));
+        colors = colors.map(|colors| gather(&colors, &sources));
+    }
+
+    let mut triangle_mesh =
+        TriangleMesh::with_materials(positions, faces, materials, material_indices);
+    if let Some(normals) = normals {
+        triangle_mesh = triangle_mesh.with_normals(normals);
+    }
+    if let Some(uvs) = uvs {
+        triangle_mesh = triangle_mesh.with_uvs(uvs);
+    }
+    if let Some(tangents) = tangents {
+        triangle_mesh = triangle_mesh.with_tangents(tangents);
+    }
+    if let Some(colors) = colors {
+        triangle_mesh = triangle_mesh.with_colors(colors);
+    }
+    triangle_mesh
+}
+
+// Per corner normals averaged from the faces around each vertex position, skipping faces that
+// meet the corner's face at more than smoothing_angle degrees so hard edges stay hard
+// Vertices are welded by position first since OBJ files split them along UV seams
+pub fn generate_smooth_normals(
+    positions: &[Vec3],
+    faces: &[[usize; 3]],
+    smoothing_angle: f32,
+) -> Vec<[Vec3; 3]> {
+    let position_key = |i: usize| positions[i].to_array().map(f32::to_bits);
+
+    // area weighted
+    let face_normals: Vec<Vec3> = faces
+        .iter()
+        .map(|face| {
+            (positions[face[1]] - positions[face[0]]).cross(positions[face[2]] - positions[face[0]])
+        })
+        .collect();
+
+    let mut position_faces: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (face_index, face) in faces.iter().enumerate() {
+        for &vertex in face {
+            position_faces
+                .entry(position_key(vertex))
+                .or_default()
+                .push(face_index);
+        }
+    }
+
+    let cos_threshold = smoothing_angle.to_radians().cos();
+
+    faces
+        .iter()
+        .zip(&face_normals)
+        .map(|(face, face_normal)| {
+            let unit_normal = face_normal.normalize_or_zero();
+            face.map(|vertex| {
+                let mut normal = Vec3::ZERO;
+                for &other in &position_faces[&position_key(vertex)] {
+                    let other_normal = face_normals[other];
+                    if unit_normal.dot(other_normal.normalize_or_zero()) >= cos_threshold {
+                        normal += other_normal;
+                    }
+                }
+                normal.try_normalize().unwrap_or(unit_normal)
+            })
+        })
+        .collect()
+}
+
+struct TextureCache<'a> {
+    parent_path: &'a Path,
+    textures: HashMap<(PathBuf, TextureUsage), Arc<ImageTexture>>,
+}
+
+impl<'a> TextureCache<'a> {
+    fn new(parent_path: &'a Path) -> Self {
+        Self {
+            parent_path,
+            textures: HashMap::new(),
+        }
+    }
+
+    // texture_spec is everything after the map_* key, options included
+    fn load(
+        &mut self,
+        material_name: &str,
+        texture_spec: &str,
+        usage: TextureUsage,
+    ) -> anyhow::Result<Arc<dyn Texture>> {
+        let (texture, options) = self.load_with_options(texture_spec, usage)?;
+        warn_texture_options(material_name, &options);
+        Ok(texture)
+    }
+
+    // Leaves the options to the caller
+    fn load_with_options(
+        &mut self,
+        texture_spec: &str,
+        usage: TextureUsage,
+    ) -> anyhow::Result<(Arc<ImageTexture>, Vec<String>)> {
+        let (file_name, options) = split_texture_options(texture_spec);
+
+        // the same file can be used as both color and data, loaded differently
+        let key = (self.parent_path.join(file_name), usage);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok((texture.clone(), options));
+        }
+        let texture = Arc::new(ImageTexture::load_for_usage(&key.0, usage)?);
+        self.textures.insert(key, texture.clone());
+        Ok((texture, options))
+    }
+}
+
+fn warn_texture_options(material_name: &str, options: &[String]) {
+    for option in options {
+        eprintln!(
+            "Warning: material \"{material_name}\": unsupported texture option \"{option}\" ignored."
+        );
+    }
+}
+
+// Splits "-bm 0.5 -clamp on file.png" into the file name and the options before it
+fn split_texture_options(texture_spec: &str) -> (String, Vec<String>) {
+    let mut words = texture_spec.split_whitespace().peekable();
+    let mut options = Vec::new();
+
+    while let Some(option) = words.next_if(|word| word.starts_with('-')) {
+        let mut arguments = vec![option];
+        // a single word for options like -clamp on and -imfchan r, numbers for the rest
+        if matches!(
+            option,
+            "-blendu" | "-blendv" | "-cc" | "-clamp" | "-imfchan" | "-type"
+        ) {
+            arguments.extend(words.next());
+        } else {
+            while let Some(argument) = words.next_if(|word| word.parse::<f32>().is_ok()) {
+                arguments.push(argument);
             }
         }
+        options.push(arguments.join(" "));
+    }
+
+    (words.collect::<Vec<_>>().join(" "), options)
+}
+
+fn parse_mtl_floats(value: &str) -> Option<Vec<f32>> {
+    value
+        .split_whitespace()
+        .map(|word| word.parse().ok())
+        .collect()
+}
 
-        let triangles = mesh.indices.len() / 3;
-        out_meshes.push(HittableList::with_capacity(triangles));
-
-        for i in 0..triangles {
-            let indices = [
-                mesh.indices[3 * i] as usize,
-                mesh.indices[3 * i + 1] as usize,
-                mesh.indices[3 * i + 2] as usize,
-            ];
-            let mut vertices = [Vec3::ZERO; 3];
-            let mut texcoords = [Vec2::ZERO; 3];
-            for (index, (vertex, texcoord)) in indices
-                .iter()
-                .zip(vertices.iter_mut().zip(texcoords.iter_mut()))
-            {
-                *vertex = Vec3::new(
-                    mesh.positions[3 * index],
-                    mesh.positions[3 * index + 1],
-                    mesh.positions[3 * index + 2],
-                );
-                *texcoord = if !mesh.texcoords.is_empty() {
-                    Vec2::new(mesh.texcoords[2 * index], mesh.texcoords[2 * index + 1])
+fn parse_mtl_color(value: &str) -> Option<Vec3> {
+    match parse_mtl_floats(value)?.as_slice() {
+        [r, g, b, ..] => Some(Vec3::new(*r, *g, *b)),
+        [gray] => Some(Vec3::splat(*gray)),
+        _ => None,
+    }
+}
+
+fn parse_mtl_float(value: &str) -> Option<f32> {
+    parse_mtl_floats(value)?.first().copied()
+}
+
+// Phong exponent to GGX roughness, through the Beckmann alpha sqrt(2 / (Ns + 2))
+fn shininess_to_roughness(shininess: f32) -> f32 {
+    (2.0 / (shininess.max(0.0) + 2.0)).sqrt().sqrt()
+}
+
+// Translates an MTL material into a LambertianMaterial when it only has a diffuse color or
+// texture, and into a PrincipledMaterial otherwise
+// map_d becomes an opacity mask cutting out the geometry
+fn translate_mtl_material(
+    mtl_material: &tobj::Material,
+    texture_cache: &mut TextureCache,
+) -> anyhow::Result<Arc<dyn Material>> {
+    let name = &mtl_material.name;
+    let warn = |key: &str| {
+        eprintln!("Warning: material \"{name}\": unsupported key \"{key}\" ignored.");
+    };
+    let solid = |value: f32| -> Arc<dyn Texture> { Arc::new(SolidColor::splat(value)) };
+
+    let base_color: Arc<dyn Texture> = match &mtl_material.diffuse_texture {
+        Some(diffuse_texture) => texture_cache.load(name, diffuse_texture, TextureUsage::Color)?,
+        None => Arc::new(SolidColor::new(
+            mtl_material
+                .diffuse
+                .map(Vec3::from)
+                .unwrap_or(Vec3::splat(0.8)),
+        )),
+    };
+
+    let mut material = PrincipledMaterial::new(base_color.clone());
+    material.specular = solid(0.0);
+    let mut is_principled = false;
+
+    if let Some(specular) = mtl_material.specular {
+        let specular = Vec3::from(specular);
+        // Ks is usually an untinted highlight strength, 0.5 maps to a 4% reflectance
+        material.specular = solid((specular.x + specular.y + specular.z) / 3.0);
+        is_principled |= specular != Vec3::ZERO;
+    }
+    if let Some(specular_texture) = &mtl_material.specular_texture {
+        material.specular = texture_cache.load(name, specular_texture, TextureUsage::Data)?;
+        is_principled = true;
+    }
+    if let Some(shininess) = mtl_material.shininess {
+        material.roughness = solid(shininess_to_roughness(shininess));
+    }
+    if let Some(refraction_index) = mtl_material.optical_density {
+        material.refraction_index = refraction_index;
+    }
+
+    let mut dissolve = mtl_material.dissolve;
+    let mut normal_map = None;
+    for (key, value) in &mtl_material.unknown_param {
+        match key.as_str() {
+            "Tr" => dissolve = dissolve.or(parse_mtl_float(value).map(|tr| 1.0 - tr)),
+            "Ke" => {
+                if let Some(emission) = parse_mtl_color(value) {
+                    if emission != Vec3::ZERO {
+                        material.emission = Arc::new(SolidColor::new(emission));
+                        material.emission_strength = 1.0;
+                        is_principled = true;
+                    }
                 } else {
-                    Vec2::ZERO
+                    warn(key);
                 }
             }
-            out_meshes[index].objects.push(Arc::new(Triangle::new(
-                vertices[0],
-                vertices[1] - vertices[0],
-                vertices[2] - vertices[0],
-                texcoords,
-                material.clone(),
-            )));
+            "map_Ke" => {
+                material.emission = texture_cache.load(name, value, TextureUsage::Color)?;
+                material.emission_strength = 1.0;
+                is_principled = true;
+            }
+            "Pr" | "Pm" | "Pc" | "Pcr" | "Ps" => {
+                let Some(value) = parse_mtl_float(value) else {
+                    warn(key);
+                    continue;
+                };
+                match key.as_str() {
+                    "Pr" => material.roughness = solid(value),
+                    "Pm" => material.metallic = solid(value),
+                    "Pc" => material.clearcoat = solid(value),
+                    "Pcr" => material.clearcoat_roughness = solid(value),
+                    _ => material.sheen = solid(value),
+                }
+                is_principled = true;
+            }
+            "map_Pr" => {
+                material.roughness = texture_cache.load(name, value, TextureUsage::Data)?;
+                is_principled = true;
+            }
+            "map_Pm" => {
+                material.metallic = texture_cache.load(name, value, TextureUsage::Data)?;
+                is_principled = true;
+            }
+            "norm" => {
+                normal_map = Some(NormalMap::TangentSpace {
+                    texture: texture_cache.load(name, value, TextureUsage::Data)?,
+                    strength: 1.0,
+                });
+            }
+            "map_Ps" => {
+                material.sheen = texture_cache.load(name, value, TextureUsage::Data)?;
+                is_principled = true;
+            }
+            _ => warn(key),
         }
+    }
 
-        out_meshes[index].update_bounding_box();
+    // d < 1 (or Tr > 0) and the glass illumination models mean a transparent dielectric
+    let glass_illumination = matches!(mtl_material.illumination_model, Some(4 | 6 | 7 | 9));
+    let transmission = match dissolve {
+        Some(dissolve) if dissolve < 1.0 => 1.0 - dissolve.max(0.0),
+        _ if glass_illumination => 1.0,
+        _ => 0.0,
+    };
+    if transmission > 0.0 {
+        material.transmission = solid(transmission);
+        if mtl_material.specular.is_none() {
+            material.specular = solid(0.5);
+        }
+        is_principled = true;
     }
 
-    Ok(out_meshes)
+    if mtl_material.ambient.is_some() {
+        warn("Ka");
+    }
+    if mtl_material.ambient_texture.is_some() {
+        warn("map_Ka");
+    }
+    if mtl_material.shininess_texture.is_some() {
+        warn("map_Ns");
+    }
+    let opacity = match &mtl_material.dissolve_texture {
+        Some(dissolve_texture) => {
+            Some(texture_cache.load(name, dissolve_texture, TextureUsage::Alpha)?)
+        }
+        None => None,
+    };
+    if let Some(normal_texture) = &mtl_material.normal_texture
+        && normal_map.is_none()
+    {
+        normal_map = Some(load_bump_map(name, normal_texture, texture_cache)?);
+    }
+
+    let material: Arc<dyn Material> = if is_principled {
+        Arc::new(material)
+    } else {
+        Arc::new(LambertianMaterial::new(base_color))
+    };
+
+    let material: Arc<dyn Material> = match opacity {
+        Some(opacity) => Arc::new(AlphaMaskedMaterial::new(material, opacity)),
+        None => material,
+    };
+
+    Ok(match normal_map {
+        Some(normal_map) => Arc::new(NormalMappedMaterial::new(material, normal_map)),
+        None => material,
+    })
+}
+
+// map_Bump and bump are height maps by the spec, but exporters often store tangent space
+// normal maps in them, so those are told apart by their mostly blue color
+fn load_bump_map(
+    material_name: &str,
+    texture_spec: &str,
+    texture_cache: &mut TextureCache,
+) -> anyhow::Result<NormalMap> {
+    // -bm scales the displacement of a full height texel, in object units of MTL_BUMP_HEIGHT
+    const MTL_BUMP_HEIGHT: f32 = 0.01;
+
+    let (texture, options) = texture_cache.load_with_options(texture_spec, TextureUsage::Data)?;
+
+    let mut multiplier = 1.0;
+    let mut unhandled_options = Vec::new();
+    for option in options {
+        match option.strip_prefix("-bm ").map(str::parse::<f32>) {
+            Some(Ok(value)) => multiplier = value,
+            _ => unhandled_options.push(option),
+        }
+    }
+    warn_texture_options(material_name, &unhandled_options);
+
+    let average = texture.average();
+    let is_normal_map =
+        average.z > 0.6 && (average.x - 0.5).abs() < 0.15 && (average.y - 0.5).abs() < 0.15;
+
+    Ok(if is_normal_map {
+        NormalMap::TangentSpace {
+            texture,
+            strength: multiplier,
+        }
+    } else {
+        NormalMap::Bump {
+            texture,
+            scale: multiplier * MTL_BUMP_HEIGHT,
+        }
+    })
 }
@@ -0,0 +1,485 @@
+use std::f32::consts::PI;
+
+use glam::Vec3;
+use rand::{Rng, RngCore};
+
+use crate::{color::luminance, onb::Onb, util::random_cosine_direction};
+
+// Isotropic GGX (Trowbridge-Reitz) distribution
+// All directions are in the local shading frame where z is the surface normal
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GgxDistribution {
+    pub alpha: f32,
+}
+
+impl GgxDistribution {
+    // Below this alpha the distribution is treated as a perfectly smooth interface
+    pub const SMOOTH_ALPHA: f32 = 1e-3;
+
+    pub fn from_roughness(roughness: f32) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            alpha: (roughness * roughness).max(1e-4),
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < Self::SMOOTH_ALPHA
+    }
+
+    pub fn d(&self, wm: Vec3) -> f32 {
+        let cos2_theta = wm.z * wm.z;
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let denom = cos2_theta * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denom * denom)
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f32 {
+        let cos2_theta = w.z * w.z;
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+        let tan2_theta = (1.0 - cos2_theta).max(0.0) / cos2_theta;
+        ((1.0 + self.alpha * self.alpha * tan2_theta).sqrt() - 1.0) * 0.5
+    }
+
+    pub fn g1(&self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated masking-shadowing
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Distribution of normals visible from w
+    pub fn visible_d(&self, w: Vec3, wm: Vec3) -> f32 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    pub fn pdf(&self, w: Vec3, wm: Vec3) -> f32 {
+        self.visible_d(w, wm)
+    }
+
+    // Sample a normal visible from w (Heitz 2018), always in the upper hemisphere
+    pub fn sample_wm(&self, w: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let mut wh = Vec3::new(self.alpha * w.x, self.alpha * w.y, w.z).normalize();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+
+        let t1 = if wh.z < 0.99999 {
+            Vec3::Z.cross(wh).normalize()
+        } else {
+            Vec3::X
+        };
+        let t2 = wh.cross(t1);
+
+        let r = rng.random::<f32>().sqrt();
+        let phi = 2.0 * PI * rng.random::<f32>();
+        let px = r * phi.cos();
+        let mut py = r * phi.sin();
+
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z) * 0.5;
+        py = (1.0 - s) * h + s * py;
+
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+// Exact unpolarized Fresnel reflectance for a dielectric interface
+// eta is the relative index of refraction (inside over outside) and cos_theta_i is measured
+// against the outward normal, so negative values mean the ray arrives from inside
+pub fn fresnel_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let mut cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta = eta;
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) * 0.5
+}
+
+// Refract wi (pointing away from the surface) through a surface with normal n
+// Returns the transmitted direction and the relative eta actually used
+pub fn refract(wi: Vec3, n: Vec3, eta: f32) -> Option<(Vec3, f32)> {
+    let mut n = n;
+    let mut eta = eta;
+    let mut cos_theta_i = n.dot(wi);
+    if cos_theta_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_theta_i = -cos_theta_i;
+        n = -n;
+    }
+
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+
+    Some((-wi / eta + (cos_theta_i / eta - cos_theta_t) * n, eta))
+}
+
+// Rough dielectric BSDF from Walter et al. 2007, "Microfacet Models for Refraction through
+// Rough Surfaces", with visible normal sampling
+// wo and wi point away from the surface and are stored in a frame whose w is the outward normal
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RoughDielectricBsdf {
+    frame: Onb,
+    wo: Vec3,
+    eta: f32,
+    distribution: GgxDistribution,
+}
+
+impl RoughDielectricBsdf {
+    pub fn new(outward_normal: Vec3, wo: Vec3, eta: f32, distribution: GgxDistribution) -> Self {
+        let frame = Onb::new(outward_normal);
+        Self {
+            frame,
+            wo: frame.to_local(wo.normalize()),
+            eta,
+            distribution,
+        }
+    }
+
+    // Generalized half vector for a local wi, facing the outward normal
+    // Also returns the relative eta along the path (1 for reflection)
+    fn half_vector(&self, wi: Vec3) -> Option<(Vec3, f32)> {
+        let cos_theta_o = self.wo.z;
+        let cos_theta_i = wi.z;
+        if cos_theta_i == 0.0 || cos_theta_o == 0.0 {
+            return None;
+        }
+
+        let reflect = cos_theta_i * cos_theta_o > 0.0;
+        let etap = if reflect {
+            1.0
+        } else if cos_theta_o > 0.0 {
+            self.eta
+        } else {
+            1.0 / self.eta
+        };
+
+        let wm = wi * etap + self.wo;
+        if wm.length_squared() == 0.0 {
+            return None;
+        }
+        let wm = wm.normalize();
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+
+        // discard backfacing microfacets
+        if wm.dot(wi) * cos_theta_i < 0.0 || wm.dot(self.wo) * cos_theta_o < 0.0 {
+            return None;
+        }
+
+        Some((wm, etap))
+    }
+
+    // BSDF value times |cos theta_i| for a world space wi
+    pub fn eval(&self, wi: Vec3) -> f32 {
+        let wi = self.frame.to_local(wi.normalize());
+        let Some((wm, etap)) = self.half_vector(wi) else {
+            return 0.0;
+        };
+
+        let fresnel = fresnel_dielectric(self.wo.dot(wm), self.eta);
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(self.wo, wi);
+
+        if etap == 1.0 {
+            d * g * fresnel / (4.0 * self.wo.z.abs())
+        } else {
+            // The 1 / eta^2 radiance scaling is left out, matching DielectricMaterial
+            let denom = wi.dot(wm) + self.wo.dot(wm) / etap;
+            let denom = denom * denom * self.wo.z;
+            d * (1.0 - fresnel) * g * (wi.dot(wm) * self.wo.dot(wm) / denom).abs()
+        }
+    }
+
+    pub fn pdf(&self, wi: Vec3) -> f32 {
+        if wi.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wi = self.frame.to_local(wi.normalize());
+        let Some((wm, etap)) = self.half_vector(wi) else {
+            return 0.0;
+        };
+
+        let reflectance = fresnel_dielectric(self.wo.dot(wm), self.eta);
+        let pdf_wm = self.distribution.pdf(self.wo, wm);
+
+        if etap == 1.0 {
+            pdf_wm / (4.0 * self.wo.dot(wm).abs()) * reflectance
+        } else {
+            let denom = wi.dot(wm) + self.wo.dot(wm) / etap;
+            let dwm_dwi = wi.dot(wm).abs() / (denom * denom);
+            pdf_wm * dwm_dwi * (1.0 - reflectance)
+        }
+    }
+
+    // Returns a world space direction, or Vec3::ZERO if the sampled path is invalid
+    // A zero direction has a pdf of zero, so the integrator treats it as absorbed
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let wm = self.distribution.sample_wm(self.wo, rng);
+        let reflectance = fresnel_dielectric(self.wo.dot(wm), self.eta);
+
+        let wi = if rng.random::<f32>() < reflectance {
+            let wi = (-self.wo).reflect(wm);
+            if wi.z * self.wo.z <= 0.0 {
+                return Vec3::ZERO;
+            }
+            wi
+        } else {
+            let Some((wi, _)) = refract(self.wo, wm, self.eta) else {
+                return Vec3::ZERO;
+            };
+            if wi.z * self.wo.z >= 0.0 {
+                return Vec3::ZERO;
+            }
+            wi
+        };
+
+        self.frame.transform(wi)
+    }
+}
+
+fn schlick(f0: Vec3, cos_theta: f32) -> Vec3 {
+    f0 + (Vec3::ONE - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+// Microfacet reflection pdf for a local wi, given the pdf of the sampled normal
+fn reflection_pdf(distribution: GgxDistribution, wo: Vec3, wi: Vec3) -> f32 {
+    let wm = (wo + wi).normalize();
+    distribution.pdf(wo, wm) / (4.0 * wo.dot(wm).abs())
+}
+
+fn sample_reflection(distribution: GgxDistribution, wo: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+    let wm = distribution.sample_wm(wo, rng);
+    let wi = (-wo).reflect(wm);
+    if wi.z <= 0.0 { Vec3::ZERO } else { wi }
+}
+
+// Evaluated (per hit point) inputs of PrincipledBsdf
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PrincipledParameters {
+    pub base_color: Vec3,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub specular: f32,
+    pub transmission: f32,
+    pub refraction_index: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+}
+
+// Layered uber BSDF in the spirit of the Disney and OpenPBR models:
+// a clearcoat layer over a mix of metal and a dielectric base, where the base is either
+// diffuse (with sheen) plus specular reflection, or rough glass
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PrincipledBsdf {
+    frame: Onb, // w faces wo
+    wo: Vec3,
+    params: PrincipledParameters,
+    specular_f0: f32,
+    sheen_color: Vec3,
+    coat_attenuation: f32,
+    distribution: GgxDistribution,
+    clearcoat_distribution: GgxDistribution,
+    glass: RoughDielectricBsdf,
+    // lobe selection probabilities: diffuse, dielectric specular, metal, glass, clearcoat
+    lobe_weights: [f32; 5],
+}
+
+impl PrincipledBsdf {
+    const CLEARCOAT_IOR: f32 = 1.5;
+
+    pub fn new(
+        params: PrincipledParameters,
+        facing_normal: Vec3,
+        outward_normal: Vec3,
+        wo: Vec3,
+    ) -> Self {
+        let frame = Onb::new(facing_normal);
+        let wo = frame.to_local(wo.normalize());
+        let wo = Vec3::new(wo.x, wo.y, wo.z.max(1e-6)).normalize();
+
+        let metallic = params.metallic.clamp(0.0, 1.0);
+        let transmission = params.transmission.clamp(0.0, 1.0);
+        let clearcoat = params.clearcoat.clamp(0.0, 1.0);
+
+        let specular_f0 = 0.08 * params.specular.clamp(0.0, 1.0);
+        let base_luminance = luminance(params.base_color);
+        let tint = if base_luminance > 0.0 {
+            params.base_color / base_luminance
+        } else {
+            Vec3::ONE
+        };
+        let sheen_color = Vec3::ONE.lerp(tint, params.sheen_tint.clamp(0.0, 1.0));
+
+        let distribution = GgxDistribution::from_roughness(params.roughness);
+        let clearcoat_distribution = GgxDistribution::from_roughness(params.clearcoat_roughness);
+        let glass = RoughDielectricBsdf::new(
+            outward_normal,
+            frame.transform(wo),
+            params.refraction_index,
+            distribution,
+        );
+
+        let coat_reflectance = clearcoat * fresnel_dielectric(wo.z, Self::CLEARCOAT_IOR);
+        let coat_attenuation = 1.0 - coat_reflectance;
+        let dielectric = (1.0 - metallic) * (1.0 - transmission);
+        let specular_reflectance = schlick(Vec3::splat(specular_f0), wo.z).x;
+
+        let mut lobe_weights = [
+            coat_attenuation * dielectric * (1.0 - specular_reflectance),
+            coat_attenuation * dielectric * specular_reflectance,
+            coat_attenuation * metallic,
+            coat_attenuation * (1.0 - metallic) * transmission,
+            coat_reflectance,
+        ];
+        let total: f32 = lobe_weights.iter().sum();
+        if total > 0.0 {
+            lobe_weights.iter_mut().for_each(|weight| *weight /= total);
+        } else {
+            lobe_weights = [1.0, 0.0, 0.0, 0.0, 0.0];
+        }
+
+        Self {
+            frame,
+            wo,
+            params: PrincipledParameters {
+                metallic,
+                transmission,
+                clearcoat,
+                ..params
+            },
+            specular_f0,
+            sheen_color,
+            coat_attenuation,
+            distribution,
+            clearcoat_distribution,
+            glass,
+            lobe_weights,
+        }
+    }
+
+    // BSDF value times |cos theta_i| for a world space wi
+    pub fn eval(&self, wi: Vec3) -> Vec3 {
+        if wi.length_squared() == 0.0 {
+            return Vec3::ZERO;
+        }
+        let wi_world = wi.normalize();
+        let wi = self.frame.to_local(wi_world);
+        let wo = self.wo;
+        let params = &self.params;
+
+        let mut f = Vec3::ZERO;
+
+        let glass_weight = (1.0 - params.metallic) * params.transmission;
+        if glass_weight > 0.0 {
+            let tint = if wi.z < 0.0 {
+                params.base_color
+            } else {
+                Vec3::ONE
+            };
+            f += self.coat_attenuation * glass_weight * tint * self.glass.eval(wi_world);
+        }
+
+        if wi.z <= 0.0 {
+            return f;
+        }
+
+        let wm = (wo + wi).normalize();
+        let dielectric_weight = (1.0 - params.metallic) * (1.0 - params.transmission);
+
+        let specular_f0 = Vec3::splat(self.specular_f0);
+        let diffuse = params.base_color
+            * std::f32::consts::FRAC_1_PI
+            * (1.0 - schlick(specular_f0, wo.z).x)
+            * wi.z;
+        let sheen = params.sheen * self.sheen_color * (1.0 - wi.dot(wm)).max(0.0).powi(5) * wi.z;
+
+        let dg = self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * wo.z);
+        let specular = schlick(specular_f0, wo.dot(wm)) * dg;
+        let metal = schlick(params.base_color, wo.dot(wm)) * dg;
+
+        f += self.coat_attenuation
+            * (dielectric_weight * (diffuse + sheen + specular) + params.metallic * metal);
+
+        if params.clearcoat > 0.0 {
+            let coat = self.clearcoat_distribution.d(wm) * self.clearcoat_distribution.g(wo, wi)
+                / (4.0 * wo.z)
+                * fresnel_dielectric(wo.dot(wm), Self::CLEARCOAT_IOR);
+            f += Vec3::splat(params.clearcoat * coat);
+        }
+
+        f
+    }
+
+    pub fn pdf(&self, wi: Vec3) -> f32 {
+        if wi.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wi_world = wi.normalize();
+        let wi = self.frame.to_local(wi_world);
+        let [diffuse, specular, metal, glass, clearcoat] = self.lobe_weights;
+
+        let mut pdf = 0.0;
+        if glass > 0.0 {
+            pdf += glass * self.glass.pdf(wi_world);
+        }
+        if wi.z > 0.0 {
+            pdf += diffuse * wi.z * std::f32::consts::FRAC_1_PI;
+            if specular + metal > 0.0 {
+                pdf += (specular + metal) * reflection_pdf(self.distribution, self.wo, wi);
+            }
+            if clearcoat > 0.0 {
+                pdf += clearcoat * reflection_pdf(self.clearcoat_distribution, self.wo, wi);
+            }
+        }
+        pdf
+    }
+
+    // Returns a world space direction, or Vec3::ZERO if the sampled path is invalid
+    pub fn sample(&self, rng: &mut dyn RngCore) -> Vec3 {
+        let [diffuse, specular, metal, glass, _clearcoat] = self.lobe_weights;
+        let lobe = rng.random::<f32>();
+
+        let wi = if lobe < diffuse {
+            random_cosine_direction(rng)
+        } else if lobe < diffuse + specular + metal {
+            sample_reflection(self.distribution, self.wo, rng)
+        } else if lobe < diffuse + specular + metal + glass {
+            return self.glass.sample(rng);
+        } else {
+            sample_reflection(self.clearcoat_distribution, self.wo, rng)
+        };
+
+        self.frame.transform(wi)
+    }
+}
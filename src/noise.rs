@@ -0,0 +1,256 @@
+use glam::Vec3;
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+
+use crate::util::random_unit_vec3;
+
+const TABLE_SIZE: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum NoiseBasis {
+    #[default]
+    Perlin,
+    Simplex,
+}
+
+// Octave settings for fbm and turbulence
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fractal {
+    pub octaves: u32,
+    pub lacunarity: f32, // frequency multiplier per octave
+    pub gain: f32,       // amplitude multiplier per octave
+}
+
+impl Fractal {
+    pub const fn new(octaves: u32) -> Self {
+        Self {
+            octaves,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self::new(7)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WorleySample {
+    pub f1: f32,         // distance to the closest feature point
+    pub f2: f32,         // distance to the second closest
+    pub cell_value: f32, // random value in [0, 1) shared by the whole cell of the closest point
+}
+
+// Gradient and permutation tables shared by every noise basis, the same seed always gives the
+// same noise
+#[derive(Clone, Debug)]
+pub struct Noise {
+    gradients: [Vec3; TABLE_SIZE],
+    permutation: [u8; 2 * TABLE_SIZE], // repeated twice to skip wrapping sums of indices
+    seed: u32,
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+
+        let gradients = std::array::from_fn(|_| random_unit_vec3(&mut rng));
+
+        let mut shuffled: [u8; TABLE_SIZE] = std::array::from_fn(|i| i as u8);
+        shuffled.shuffle(&mut rng);
+        let permutation = std::array::from_fn(|i| shuffled[i % TABLE_SIZE]);
+
+        Self {
+            gradients,
+            permutation,
+            seed: rng.random(),
+        }
+    }
+
+    pub fn sample(&self, basis: NoiseBasis, point: Vec3) -> f32 {
+        match basis {
+            NoiseBasis::Perlin => self.perlin(point),
+            NoiseBasis::Simplex => self.simplex(point),
+        }
+    }
+
+    fn hash(&self, x: i32, y: i32, z: i32) -> usize {
+        let p = &self.permutation;
+        let x = (x & 255) as usize;
+        let y = (y & 255) as usize;
+        let z = (z & 255) as usize;
+        p[p[p[x] as usize + y] as usize + z] as usize
+    }
+
+    fn gradient(&self, x: i32, y: i32, z: i32) -> Vec3 {
+        self.gradients[self.hash(x, y, z)]
+    }
+
+    // Gradient noise with quintic fade, roughly in [-1, 1]
+    pub fn perlin(&self, point: Vec3) -> f32 {
+        let cell = point.floor();
+        let f = point - cell;
+        let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+        let fade = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+
+        let mut corners = [0.0; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let (dx, dy, dz) = ((i & 1) as i32, ((i >> 1) & 1) as i32, ((i >> 2) & 1) as i32);
+            let offset = Vec3::new(dx as f32, dy as f32, dz as f32);
+            *corner = self.gradient(x + dx, y + dy, z + dz).dot(f - offset);
+        }
+
+        let lerp = |a: f32, b: f32, t: f32| a + t * (b - a);
+        let x00 = lerp(corners[0], corners[1], fade.x);
+        let x10 = lerp(corners[2], corners[3], fade.x);
+        let x01 = lerp(corners[4], corners[5], fade.x);
+        let x11 = lerp(corners[6], corners[7], fade.x);
+        let y0 = lerp(x00, x10, fade.y);
+        let y1 = lerp(x01, x11, fade.y);
+        // unit gradients rarely go past 0.75, rescaled to fill [-1, 1]
+        lerp(y0, y1, fade.z) * 1.3
+    }
+
+    // 3D simplex noise (Gustavson), roughly in [-1, 1], cheaper with fewer grid artifacts
+    pub fn simplex(&self, point: Vec3) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+
+        // skew into the simplex grid to find the containing cell
+        let skew = (point.x + point.y + point.z) * F3;
+        let cell = (point + skew).floor();
+        let unskew = (cell.x + cell.y + cell.z) * G3;
+        let p0 = point - (cell - unskew);
+
+        // the simplex corners in between, ordered by the largest coordinate
+        let (o1, o2) = if p0.x >= p0.y {
+            if p0.y >= p0.z {
+                (Vec3::X, Vec3::new(1.0, 1.0, 0.0))
+            } else if p0.x >= p0.z {
+                (Vec3::X, Vec3::new(1.0, 0.0, 1.0))
+            } else {
+                (Vec3::Z, Vec3::new(1.0, 0.0, 1.0))
+            }
+        } else if p0.y < p0.z {
+            (Vec3::Z, Vec3::new(0.0, 1.0, 1.0))
+        } else if p0.x < p0.z {
+            (Vec3::Y, Vec3::new(0.0, 1.0, 1.0))
+        } else {
+            (Vec3::Y, Vec3::new(1.0, 1.0, 0.0))
+        };
+
+        let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+        let mut sum = 0.0;
+        for (offset, corner_unskew) in [
+            (Vec3::ZERO, 0.0),
+            (o1, G3),
+            (o2, 2.0 * G3),
+            (Vec3::ONE, 3.0 * G3),
+        ] {
+            let d = p0 - offset + corner_unskew;
+            let t = 0.6 - d.length_squared();
+            if t > 0.0 {
+                let gradient = self.gradient(
+                    x + offset.x as i32,
+                    y + offset.y as i32,
+                    z + offset.z as i32,
+                );
+                sum += t * t * t * t * gradient.dot(d);
+            }
+        }
+        sum * 40.0
+    }
+
+    // Sum of octaves of the basis, normalized to roughly [-1, 1]
+    pub fn fbm(&self, basis: NoiseBasis, point: Vec3, fractal: Fractal) -> f32 {
+        self.octaves(fractal, point, |p| self.sample(basis, p))
+    }
+
+    // fbm of the absolute noise, in [0, 1], with creases where the noise crosses zero
+    pub fn turbulence(&self, basis: NoiseBasis, point: Vec3, fractal: Fractal) -> f32 {
+        self.octaves(fractal, point, |p| self.sample(basis, p).abs())
+    }
+
+    fn octaves(&self, fractal: Fractal, point: Vec3, noise: impl Fn(Vec3) -> f32) -> f32 {
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for _ in 0..fractal.octaves.max(1) {
+            sum += amplitude * noise(frequency * point);
+            total_amplitude += amplitude;
+            amplitude *= fractal.gain;
+            frequency *= fractal.lacunarity;
+        }
+        sum / total_amplitude
+    }
+
+    // Cellular noise with one feature point per unit cell
+    pub fn worley(&self, point: Vec3) -> WorleySample {
+        let cell = point.floor();
+        let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+        let mut f1 = f32::INFINITY;
+        let mut f2 = f32::INFINITY;
+        let mut closest_hash = 0;
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let hash = self.cell_hash(x + dx, y + dy, z + dz);
+                    let feature = Vec3::new((x + dx) as f32, (y + dy) as f32, (z + dz) as f32)
+                        + Vec3::new(
+                            unit_float(hash),
+                            unit_float(mix_bits(hash ^ 0x68e31da4)),
+                            unit_float(mix_bits(hash ^ 0xb5297a4d)),
+                        );
+
+                    let distance = feature.distance(point);
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                        closest_hash = hash;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+
+        WorleySample {
+            f1,
+            f2,
+            cell_value: unit_float(mix_bits(closest_hash ^ 0x1b56c4e9)),
+        }
+    }
+
+    fn cell_hash(&self, x: i32, y: i32, z: i32) -> u32 {
+        let mut hash = self.seed;
+        for coordinate in [x, y, z] {
+            hash = mix_bits(hash ^ coordinate as u32);
+        }
+        hash
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+// Integer finalizer from murmur3
+fn mix_bits(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^= x >> 16;
+    x
+}
+
+fn unit_float(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
@@ -20,4 +20,8 @@ impl Onb {
     pub fn transform(&self, v: Vec3) -> Vec3 {
         (v.x * self.u) + (v.y * self.v) + (v.z * self.w)
     }
+
+    pub fn to_local(self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.u), v.dot(self.v), v.dot(self.w))
+    }
 }
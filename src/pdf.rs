@@ -5,6 +5,7 @@ use rand::{Rng, RngCore};
 
 use crate::{
     hit::Hittable,
+    microfacet::{PrincipledBsdf, RoughDielectricBsdf},
     onb::Onb,
     util::{random_cosine_direction, random_unit_vec3},
 };
@@ -52,6 +53,48 @@ impl Pdf for CosinePdf {
     }
 }
 
+#[derive(Debug)]
+pub struct RoughDielectricPdf {
+    bsdf: RoughDielectricBsdf,
+}
+
+impl RoughDielectricPdf {
+    pub fn new(bsdf: RoughDielectricBsdf) -> Self {
+        Self { bsdf }
+    }
+}
+
+impl Pdf for RoughDielectricPdf {
+    fn value(&self, direction: Vec3, _rng: &mut dyn RngCore) -> f32 {
+        self.bsdf.pdf(direction)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        self.bsdf.sample(rng)
+    }
+}
+
+#[derive(Debug)]
+pub struct PrincipledPdf {
+    bsdf: PrincipledBsdf,
+}
+
+impl PrincipledPdf {
+    pub fn new(bsdf: PrincipledBsdf) -> Self {
+        Self { bsdf }
+    }
+}
+
+impl Pdf for PrincipledPdf {
+    fn value(&self, direction: Vec3, _rng: &mut dyn RngCore) -> f32 {
+        self.bsdf.pdf(direction)
+    }
+
+    fn generate(&self, rng: &mut dyn RngCore) -> Vec3 {
+        self.bsdf.sample(rng)
+    }
+}
+
 #[derive(Debug)]
 pub struct HittablePdf {
     objects: Arc<dyn Hittable>,
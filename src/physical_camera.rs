@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use glam::Vec2;
+
+use crate::{camera::Camera, hit::Hittable, projection::PerspectiveProjection};
+
+pub const FULL_FRAME_SENSOR: Vec2 = Vec2::new(36.0, 24.0); // mm
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Focus {
+    // the nearest distance that still keeps infinity acceptably sharp
+    Hyperfocal,
+    Distance(f32), // scene units, along the view direction
+    // first surface hit through the center of this pixel, hyperfocal when nothing is hit
+    Auto { x: u32, y: u32 },
+}
+
+// Camera settings as a photographer would give them, turned into a thin lens Camera
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalCamera {
+    pub sensor_size: Vec2, // mm, width x height
+    pub focal_length: f32, // mm
+    pub f_number: f32,
+    pub shutter_speed: f32, // seconds, the motion blur interval from shutter_open
+    pub shutter_open: f32,
+    pub iso: f32,
+    pub focus: Focus,
+    pub units_per_meter: f32, // scene scale, for the lens dimensions
+}
+
+impl PhysicalCamera {
+    pub fn new(
+        sensor_size: Vec2,
+        focal_length: f32,
+        f_number: f32,
+        shutter_speed: f32,
+        iso: f32,
+    ) -> Self {
+        Self {
+            sensor_size,
+            focal_length,
+            f_number,
+            shutter_speed,
+            shutter_open: 0.0,
+            iso,
+            focus: Focus::Hyperfocal,
+            units_per_meter: 1.0,
+        }
+    }
+
+    pub fn with_focus(mut self, focus: Focus) -> Self {
+        self.focus = focus;
+        self
+    }
+
+    pub fn with_shutter_open(mut self, shutter_open: f32) -> Self {
+        self.shutter_open = shutter_open;
+        self
+    }
+
+    pub fn with_units_per_meter(mut self, units_per_meter: f32) -> Self {
+        self.units_per_meter = units_per_meter;
+        self
+    }
+
+    // Vertical field of view in degrees, for the largest part of the sensor with the image
+    // aspect ratio
+    pub fn vfov(&self, aspect_ratio: f32) -> f32 {
+        let gate_height = self.sensor_size.y.min(self.sensor_size.x / aspect_ratio);
+        2.0 * (gate_height / (2.0 * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    // Radius of the entrance pupil in scene units
+    pub fn aperture_radius(&self) -> f32 {
+        self.mm_to_units(self.focal_length / (2.0 * self.f_number))
+    }
+
+    // Exposure value at ISO 100
+    pub fn ev100(&self) -> f32 {
+        (self.f_number * self.f_number / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    // Radiance scale from the saturation based sensitivity, so a luminance of
+    // 1.2 * 2^ev100 cd/m^2 maps to 1
+    pub fn exposure(&self) -> f32 {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+
+    // In scene units, with a circle of confusion of 1/1500 of the sensor diagonal
+    pub fn hyperfocal_distance(&self) -> f32 {
+        let circle_of_confusion = self.sensor_size.length() / 1500.0;
+        self.mm_to_units(
+            self.focal_length * self.focal_length / (self.f_number * circle_of_confusion)
+                + self.focal_length,
+        )
+    }
+
+    // camera with the field of view, depth of field, shutter and exposure of these settings
+    // Its placement, image size and render settings are kept
+    // world is only traced for Focus::Auto
+    pub fn apply(&self, camera: Camera, world: &impl Hittable) -> Camera {
+        let vfov = self.vfov(camera.image_width() as f32 / camera.image_height() as f32);
+
+        // the pinhole at this field of view first, for autofocus to aim through
+        let camera = camera
+            .with_projection(Arc::new(PerspectiveProjection::new(vfov)))
+            .with_shutter(self.shutter_open, self.shutter_open + self.shutter_speed)
+            .with_exposure(self.exposure());
+
+        let focus_dist = match self.focus {
+            Focus::Hyperfocal => self.hyperfocal_distance(),
+            Focus::Distance(distance) => distance,
+            Focus::Auto { x, y } => camera
+                .focus_distance_at(world, x, y)
+                .unwrap_or_else(|| self.hyperfocal_distance()),
+        };
+
+        camera.with_projection(Arc::new(PerspectiveProjection {
+            vfov,
+            focus_dist,
+            lens_radius: self.aperture_radius(),
+        }))
+    }
+
+    fn mm_to_units(&self, mm: f32) -> f32 {
+        mm * 0.001 * self.units_per_meter
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3};
+use rand::RngCore;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+};
+
+// Infinite plane through point, for ground and backdrops
+// Kept outside the BVH since it has no finite bounding box
+#[derive(Clone, Debug)]
+pub struct Plane {
+    pub point: Vec3,
+    pub tile_size: f32, // world units per [0,1] of uv, with uvs counting up from point
+    pub material: Arc<dyn Material>,
+    frame: Onb, // w is the normal
+    bbox: Aabb,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Self {
+        let frame = Onb::new(normal);
+
+        // flat along an axis the plane is perpendicular to, infinite along the others
+        let mut bbox = Aabb::EVERYTHING;
+        for axis in 0..3 {
+            if frame.w[axis].abs() == 1.0 {
+                bbox[axis] = Interval::new(point[axis], point[axis]);
+            }
+        }
+
+        Self {
+            point,
+            tile_size: 1.0,
+            material,
+            frame,
+            bbox: bbox.padded_to_mins(),
+        }
+    }
+
+    // The ground at height y, facing up
+    pub fn ground(y: f32, material: Arc<dyn Material>) -> Self {
+        Self::new(Vec3::new(0.0, y, 0.0), Vec3::Y, material)
+    }
+
+    pub const fn with_tile_size(mut self, tile_size: f32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.frame.w
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let denominator = self.frame.w.dot(ray.direction);
+
+        // ray is parallel to plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = self.frame.w.dot(self.point - ray.origin) / denominator;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let intersection = ray.at(t);
+        let relative = self.frame.to_local(intersection - self.point);
+        let uv = Vec2::new(relative.x, relative.y) / self.tile_size;
+
+        let mut hit_record = HitRecord::new(
+            ray,
+            intersection,
+            self.frame.w,
+            self.material.clone(),
+            t,
+            uv,
+        );
+        hit_record.dpdu = self.frame.u * self.tile_size;
+        hit_record.dpdv = self.frame.v * self.tile_size;
+
+        self.material
+            .alpha_test(&hit_record, rng)
+            .then_some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // infinite area, so never sampled as a light
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3, _rng: &mut dyn RngCore) -> f32 {
+        0.0
+    }
+
+    fn random(&self, _origin: Vec3, _rng: &mut dyn RngCore) -> Vec3 {
+        self.frame.w
+    }
+}
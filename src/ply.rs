@@ -0,0 +1,554 @@
+use std::{fmt::Debug, path::Path, sync::Arc};
+
+use anyhow::Context;
+use glam::Vec3;
+
+use crate::{
+    color::srgb_to_linear,
+    hittable_list::HittableList,
+    material::Material,
+    mesh::{DEFAULT_SMOOTHING_ANGLE, IndexedMesh, triangle_mesh_from_faces},
+};
+
+// Polygon File Format, ASCII or binary
+// Vertex colors are kept on the triangles, a VertexColorTexture in material shows them
+
+pub fn load_ply_meshes(
+    path: impl AsRef<Path> + Debug,
+    material: Arc<dyn Material>,
+) -> anyhow::Result<Vec<HittableList>> {
+    load_ply_meshes_with_smoothing(path, material, DEFAULT_SMOOTHING_ANGLE)
+}
+
+// smoothing_angle is in degrees and only used for meshes without normals, 0 for flat shading
+// A PLY file holds one mesh, returned as a list holding a single TriangleMesh
+pub fn load_ply_meshes_with_smoothing(
+    path: impl AsRef<Path> + Debug,
+    material: Arc<dyn Material>,
+    smoothing_angle: f32,
+) -> anyhow::Result<Vec<HittableList>> {
+    let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {path:?}."))?;
+    let mesh = parse_ply(&bytes).with_context(|| format!("Failed to parse PLY file {path:?}."))?;
+
+    eprintln!(
+        "Loading PLY mesh {path:?} with {} vertices and {} triangles...",
+        mesh.positions.len(),
+        mesh.faces.len()
+    );
+
+    let mut list = HittableList::new();
+    let indexed_mesh = IndexedMesh {
+        positions: mesh.positions,
+        faces: mesh.faces,
+        normals: mesh.normals,
+        colors: mesh.colors,
+        ..Default::default()
+    };
+    list.add(Arc::new(triangle_mesh_from_faces(
+        indexed_mesh,
+        smoothing_angle,
+        vec![material],
+    )));
+    Ok(vec![list])
+}
+
+struct PlyMesh {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    colors: Option<Vec<Vec3>>, // linear
+    faces: Vec<[usize; 3]>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ScalarType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> anyhow::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Self::Int8,
+            "uchar" | "uint8" => Self::Uint8,
+            "short" | "int16" => Self::Int16,
+            "ushort" | "uint16" => Self::Uint16,
+            "int" | "int32" => Self::Int32,
+            "uint" | "uint32" => Self::Uint32,
+            "float" | "float32" => Self::Float32,
+            "double" | "float64" => Self::Float64,
+            _ => anyhow::bail!("Unknown property type \"{name}\"."),
+        })
+    }
+
+    const fn size(self) -> usize {
+        match self {
+            Self::Int8 | Self::Uint8 => 1,
+            Self::Int16 | Self::Uint16 => 2,
+            Self::Int32 | Self::Uint32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    // Integer colors are 0-255, or 0-65535 for 16 bits, floats are already in [0, 1]
+    const fn color_scale(self) -> Option<f64> {
+        match self {
+            Self::Int8 | Self::Uint8 => Some(255.0),
+            Self::Int16 | Self::Uint16 => Some(65535.0),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum PropertyKind {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+fn parse_ply(bytes: &[u8]) -> anyhow::Result<PlyMesh> {
+    let (format, elements, body) = parse_header(bytes)?;
+    let mut reader = match format {
+        PlyFormat::Ascii => PlyReader::Ascii(
+            std::str::from_utf8(body)
+                .context("ASCII body isn't valid UTF-8.")?
+                .split_ascii_whitespace(),
+        ),
+        PlyFormat::BinaryLittleEndian => PlyReader::Binary {
+            bytes: body,
+            big_endian: false,
+        },
+        PlyFormat::BinaryBigEndian => PlyReader::Binary {
+            bytes: body,
+            big_endian: true,
+        },
+    };
+
+    let mut mesh = PlyMesh {
+        positions: Vec::new(),
+        normals: None,
+        colors: None,
+        faces: Vec::new(),
+    };
+
+    // faces are checked against the header's count, whichever element comes first
+    let vertex_count = elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .map_or(0, |element| element.count);
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut reader, element, &mut mesh)?,
+            "face" => read_faces(&mut reader, element, vertex_count, &mut mesh)?,
+            _ => {
+                // still has to be read through to reach the elements after it
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        skip_property(&mut reader, &property.kind)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(mesh)
+}
+
+fn parse_header(bytes: &[u8]) -> anyhow::Result<(PlyFormat, Vec<Element>, &[u8])> {
+    const END_HEADER: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .context("Missing end_header.")?;
+    // the body starts after the line break following end_header
+    let body_start = bytes[end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(bytes.len(), |offset| end + offset + 1);
+
+    let header = std::str::from_utf8(&bytes[..end]).context("Header isn't valid UTF-8.")?;
+    let mut lines = header.lines().map(str::trim);
+    anyhow::ensure!(lines.next() == Some("ply"), "Missing the ply magic number.");
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => anyhow::bail!("Unknown format \"{name}\"."),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().context("Invalid element count.")?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .context("Property before any element.")?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::List {
+                        count: ScalarType::parse(count)?,
+                        item: ScalarType::parse(item)?,
+                    },
+                });
+            }
+            ["property", scalar, name] => {
+                let element = elements
+                    .last_mut()
+                    .context("Property before any element.")?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::Scalar(ScalarType::parse(scalar)?),
+                });
+            }
+            ["comment" | "obj_info", ..] | [] => {}
+            _ => anyhow::bail!("Unexpected header line \"{line}\"."),
+        }
+    }
+
+    let format = format.context("Missing format line.")?;
+    Ok((format, elements, &bytes[body_start..]))
+}
+
+enum PlyReader<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl PlyReader<'_> {
+    // Room to reserve for count records of an element, no more than the rest of a binary body
+    // can hold so a header claiming billions of records can't exhaust memory
+    fn capacity_for(&self, element: &Element) -> usize {
+        match self {
+            // the rest of an ASCII body is behind the word iterator, so it grows as it is read
+            PlyReader::Ascii(_) => 0,
+            PlyReader::Binary { bytes, .. } => {
+                let record_size: usize = element
+                    .properties
+                    .iter()
+                    .map(|property| match property.kind {
+                        PropertyKind::Scalar(scalar) => scalar.size(),
+                        PropertyKind::List { count, .. } => count.size(),
+                    })
+                    .sum();
+                element.count.min(bytes.len() / record_size.max(1))
+            }
+        }
+    }
+
+    // f64 holds every PLY scalar type exactly, up to 32 bit integers
+    fn read(&mut self, scalar: ScalarType) -> anyhow::Result<f64> {
+        match self {
+            PlyReader::Ascii(words) => {
+                let word = words.next().context("Unexpected end of file.")?;
+                word.parse()
+                    .with_context(|| format!("Invalid number \"{word}\"."))
+            }
+            PlyReader::Binary { bytes, big_endian } => {
+                let size = scalar.size();
+                anyhow::ensure!(bytes.len() >= size, "Unexpected end of file.");
+                let (value, rest) = bytes.split_at(size);
+                *bytes = rest;
+
+                let mut buffer = [0; 8];
+                buffer[..size].copy_from_slice(value);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buffer;
+                Ok(match scalar {
+                    ScalarType::Int8 => b0 as i8 as f64,
+                    ScalarType::Uint8 => b0 as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::Uint16 => u16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::Uint32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+}
+
+// Reads through a property nothing is taken from
+fn skip_property(reader: &mut PlyReader, kind: &PropertyKind) -> anyhow::Result<()> {
+    match *kind {
+        PropertyKind::Scalar(scalar) => {
+            reader.read(scalar)?;
+        }
+        PropertyKind::List { count, item } => {
+            for _ in 0..list_length(reader, count)? {
+                reader.read(item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn list_length(reader: &mut PlyReader, count: ScalarType) -> anyhow::Result<usize> {
+    let count = reader.read(count)?;
+    anyhow::ensure!(
+        count >= 0.0 && count.fract() == 0.0,
+        "Invalid list length {count}."
+    );
+    Ok(count as usize)
+}
+
+fn read_vertices(
+    reader: &mut PlyReader,
+    element: &Element,
+    mesh: &mut PlyMesh,
+) -> anyhow::Result<()> {
+    let find_all = |names: [&[&str]; 3]| -> Option<[usize; 3]> {
+        Some([
+            element.find(names[0])?,
+            element.find(names[1])?,
+            element.find(names[2])?,
+        ])
+    };
+    let position = find_all([&["x"], &["y"], &["z"]]).context("Vertices without positions.")?;
+    let normal = find_all([&["nx"], &["ny"], &["nz"]]);
+    let color = find_all([
+        &["red", "r", "diffuse_red"],
+        &["green", "g", "diffuse_green"],
+        &["blue", "b", "diffuse_blue"],
+    ]);
+    let color_scale = color.and_then(|[red, ..]| match element.properties[red].kind {
+        PropertyKind::Scalar(scalar) => scalar.color_scale(),
+        PropertyKind::List { .. } => None,
+    });
+
+    // the position, normal or color component each property is read into
+    let mut targets = vec![None; element.properties.len()];
+    for (attribute, indices) in [Some(position), normal, color].into_iter().enumerate() {
+        for (component, index) in indices.into_iter().flatten().enumerate() {
+            targets[index] = Some((attribute, component));
+        }
+    }
+
+    mesh.positions.reserve(reader.capacity_for(element));
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    for _ in 0..element.count {
+        let mut attributes = [Vec3::ZERO; 3];
+        for (property, target) in element.properties.iter().zip(&targets) {
+            match (&property.kind, target) {
+                (&PropertyKind::Scalar(scalar), &Some((attribute, component))) => {
+                    attributes[attribute][component] = reader.read(scalar)? as f32;
+                }
+                (kind, _) => skip_property(reader, kind)?,
+            }
+        }
+        let [vertex_position, vertex_normal, vertex_color] = attributes;
+
+        mesh.positions.push(vertex_position);
+        if normal.is_some() {
+            normals.push(vertex_normal);
+        }
+        if color.is_some() {
+            // integer colors are sRGB encoded bytes, float colors are taken as linear
+            colors.push(match color_scale {
+                Some(scale) => vertex_color.map(|c| srgb_to_linear(c / scale as f32)),
+                None => vertex_color,
+            });
+        }
+    }
+
+    mesh.normals = normal.map(|_| normals);
+    mesh.colors = color.map(|_| colors);
+    Ok(())
+}
+
+// Polygons are split into fans
+fn read_faces(
+    reader: &mut PlyReader,
+    element: &Element,
+    vertex_count: usize,
+    mesh: &mut PlyMesh,
+) -> anyhow::Result<()> {
+    let indices = element
+        .find(&["vertex_indices", "vertex_index"])
+        .context("Faces without vertex indices.")?;
+    let PropertyKind::List { count, item } = element.properties[indices].kind else {
+        anyhow::bail!("Face vertex indices aren't a list.");
+    };
+
+    mesh.faces.reserve(reader.capacity_for(element));
+    let mut polygon = Vec::new();
+    for face in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            if i != indices {
+                skip_property(reader, &property.kind)?;
+                continue;
+            }
+
+            polygon.clear();
+            for _ in 0..list_length(reader, count)? {
+                let index = reader.read(item)?;
+                if !(index >= 0.0 && index.fract() == 0.0 && (index as usize) < vertex_count) {
+                    anyhow::bail!("Face {face} has an invalid vertex index {index}.");
+                }
+                polygon.push(index as usize);
+            }
+            for j in 1..polygon.len().saturating_sub(1) {
+                mesh.faces.push([polygon[0], polygon[j], polygon[j + 1]]);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD_HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    const QUAD_POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+
+    fn binary_quad(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = format!("ply\nformat {format} 1.0\n{QUAD_HEADER}").into_bytes();
+        for position in QUAD_POSITIONS {
+            for value in position {
+                bytes.extend(if big_endian {
+                    value.to_be_bytes()
+                } else {
+                    value.to_le_bytes()
+                });
+            }
+            bytes.extend([255, 0, 0]);
+        }
+        bytes.push(4);
+        for index in [0i32, 1, 2, 3] {
+            bytes.extend(if big_endian {
+                index.to_be_bytes()
+            } else {
+                index.to_le_bytes()
+            });
+        }
+        bytes
+    }
+
+    fn assert_quad(mesh: &PlyMesh) {
+        let positions: Vec<Vec3> = QUAD_POSITIONS.map(Vec3::from_array).to_vec();
+        assert_eq!(mesh.positions, positions);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.normals.is_none());
+        assert_eq!(mesh.colors, Some(vec![Vec3::X; 4]));
+    }
+
+    #[test]
+    fn ascii() {
+        let ply = format!(
+            "ply\nformat ascii 1.0\ncomment a quad\n{QUAD_HEADER}\
+             0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 255 0 0\n0 1 0 255 0 0\n4 0 1 2 3\n"
+        );
+        assert_quad(&parse_ply(ply.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        assert_quad(&parse_ply(&binary_quad("binary_little_endian", false)).unwrap());
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        assert_quad(&parse_ply(&binary_quad("binary_big_endian", true)).unwrap());
+    }
+
+    #[test]
+    fn skips_other_properties_and_elements() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float confidence\n\
+                   property float x\nproperty float y\nproperty float z\n\
+                   element face 1\nproperty list uchar int vertex_indices\nproperty uchar flags\n\
+                   element edge 1\nproperty int vertex1\nproperty int vertex2\nend_header\n\
+                   0.5 0 0 0\n0.5 1 0 0\n0.5 0 1 0\n3 0 1 2 7\n0 1\n";
+        let mesh = parse_ply(ply.as_bytes()).unwrap();
+        assert_eq!(mesh.positions[2], Vec3::Y);
+        assert_eq!(mesh.faces, vec![[0, 1, 2]]);
+        assert!(mesh.colors.is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_face_indices() {
+        for index in ["-1", "3", "1.5"] {
+            let ply = format!(
+                "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                 property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+                 end_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 {index}\n"
+            );
+            assert!(parse_ply(ply.as_bytes()).is_err(), "index {index}");
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_body() {
+        // a header claiming far more vertices than the body holds
+        let mut bytes = binary_quad("binary_little_endian", false);
+        let count = bytes
+            .windows(8)
+            .position(|window| window == b"vertex 4")
+            .unwrap()
+            + 7;
+        bytes.splice(count..count + 1, *b"4000000000");
+        assert!(parse_ply(&bytes).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3};
+
+use crate::{
+    noise::{Fractal, Noise, NoiseBasis},
+    texture::{SolidColor, Texture, TextureContext},
+};
+
+// Solid textures evaluate their noise at the hit point scaled by scale, colors are textures so
+// any of these can feed another
+
+fn lerp_textures(
+    a: &Arc<dyn Texture>,
+    b: &Arc<dyn Texture>,
+    t: f32,
+    context: &TextureContext,
+) -> Vec3 {
+    a.value(context).lerp(b.value(context), t)
+}
+
+fn solid(r: f32, g: f32, b: f32) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::from_rgb(r, g, b))
+}
+
+// Grayscale fbm, or turbulence, in [0, 1]
+#[derive(Clone, Debug)]
+pub struct NoiseTexture {
+    pub noise: Noise,
+    pub basis: NoiseBasis,
+    pub scale: f32,
+    pub fractal: Fractal,
+    pub turbulent: bool,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f32) -> Self {
+        Self {
+            noise: Noise::default(),
+            basis: NoiseBasis::default(),
+            scale,
+            fractal: Fractal::default(),
+            turbulent: false,
+        }
+    }
+
+    pub fn turbulence(scale: f32) -> Self {
+        Self {
+            turbulent: true,
+            ..Self::new(scale)
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let point = self.scale * context.point;
+        let value = if self.turbulent {
+            self.noise.turbulence(self.basis, point, self.fractal)
+        } else {
+            0.5 * (1.0 + self.noise.fbm(self.basis, point, self.fractal))
+        };
+        Vec3::splat(value.clamp(0.0, 1.0))
+    }
+}
+
+// Sine bands along z, pushed around by turbulence, as in the book
+#[derive(Clone, Debug)]
+pub struct MarbleTexture {
+    pub noise: Noise,
+    pub scale: f32,
+    pub fractal: Fractal,
+    pub distortion: f32,
+    pub base: Arc<dyn Texture>,
+    pub vein: Arc<dyn Texture>,
+}
+
+impl MarbleTexture {
+    pub fn new(scale: f32, base: Arc<dyn Texture>, vein: Arc<dyn Texture>) -> Self {
+        Self {
+            noise: Noise::default(),
+            scale,
+            fractal: Fractal::default(),
+            distortion: 10.0,
+            base,
+            vein,
+        }
+    }
+}
+
+impl Default for MarbleTexture {
+    fn default() -> Self {
+        Self::new(4.0, solid(0.9, 0.9, 0.88), solid(0.2, 0.2, 0.25))
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        // the turbulence stays at unit scale so the veins keep their shape as the bands tighten
+        let turbulence = self
+            .noise
+            .turbulence(NoiseBasis::Perlin, context.point, self.fractal);
+        let band =
+            0.5 * (1.0 + (self.scale * context.point.z + self.distortion * turbulence).sin());
+        lerp_textures(&self.vein, &self.base, band, context)
+    }
+}
+
+// Concentric rings around the y axis, wobbled by fbm
+#[derive(Clone, Debug)]
+pub struct WoodTexture {
+    pub noise: Noise,
+    pub scale: f32, // rings per unit
+    pub fractal: Fractal,
+    pub distortion: f32, // in rings
+    pub light: Arc<dyn Texture>,
+    pub dark: Arc<dyn Texture>,
+}
+
+impl WoodTexture {
+    pub fn new(scale: f32, light: Arc<dyn Texture>, dark: Arc<dyn Texture>) -> Self {
+        Self {
+            noise: Noise::default(),
+            scale,
+            fractal: Fractal::new(3),
+            distortion: 0.6,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Default for WoodTexture {
+    fn default() -> Self {
+        Self::new(8.0, solid(0.72, 0.5, 0.3), solid(0.42, 0.25, 0.12))
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let point = self.scale * context.point;
+        let radius = Vec2::new(point.x, point.z).length();
+        // stretched along the grain so the wobble varies slowly with height
+        let wobble = self.noise.fbm(
+            NoiseBasis::Perlin,
+            point * Vec3::new(1.0, 0.1, 1.0),
+            self.fractal,
+        );
+        let ring = (radius + self.distortion * wobble).rem_euclid(1.0);
+        // sharp late wood edge, soft early wood
+        lerp_textures(&self.light, &self.dark, ring * ring * ring, context)
+    }
+}
+
+// Thresholded fbm, coverage is the fraction of sky covered
+#[derive(Clone, Debug)]
+pub struct CloudsTexture {
+    pub noise: Noise,
+    pub scale: f32,
+    pub fractal: Fractal,
+    pub coverage: f32,
+    pub sharpness: f32,
+    pub sky: Arc<dyn Texture>,
+    pub cloud: Arc<dyn Texture>,
+}
+
+impl CloudsTexture {
+    pub fn new(scale: f32, sky: Arc<dyn Texture>, cloud: Arc<dyn Texture>) -> Self {
+        Self {
+            noise: Noise::default(),
+            scale,
+            fractal: Fractal::new(6),
+            coverage: 0.5,
+            sharpness: 4.0,
+            sky,
+            cloud,
+        }
+    }
+}
+
+impl Default for CloudsTexture {
+    fn default() -> Self {
+        Self::new(1.0, solid(0.3, 0.5, 0.9), solid(1.0, 1.0, 1.0))
+    }
+}
+
+impl Texture for CloudsTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let point = self.scale * context.point;
+        let density = 0.5 * (1.0 + self.noise.fbm(NoiseBasis::Simplex, point, self.fractal));
+        let t = ((density - 1.0 + self.coverage) * self.sharpness).clamp(0.0, 1.0);
+        lerp_textures(&self.sky, &self.cloud, t, context)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum CellularPattern {
+    // flat random blend of the two colors per cell
+    #[default]
+    Cells,
+    // second color on the borders between cells
+    Edges,
+    // distance to the closest feature point
+    Distance,
+}
+
+// Worley noise patterns, for scales, stones and cracks
+#[derive(Clone, Debug)]
+pub struct CellularTexture {
+    pub noise: Noise,
+    pub scale: f32,
+    pub pattern: CellularPattern,
+    pub edge_width: f32,
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+}
+
+impl CellularTexture {
+    pub fn new(
+        scale: f32,
+        pattern: CellularPattern,
+        a: Arc<dyn Texture>,
+        b: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            noise: Noise::default(),
+            scale,
+            pattern,
+            edge_width: 0.05,
+            a,
+            b,
+        }
+    }
+}
+
+impl Texture for CellularTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let sample = self.noise.worley(self.scale * context.point);
+        let t = match self.pattern {
+            CellularPattern::Cells => sample.cell_value,
+            CellularPattern::Edges => {
+                1.0 - ((sample.f2 - sample.f1) / self.edge_width.max(1e-6)).clamp(0.0, 1.0)
+            }
+            CellularPattern::Distance => sample.f1.clamp(0.0, 1.0),
+        };
+        lerp_textures(&self.a, &self.b, t, context)
+    }
+}
+
+// Checkerboard in texture space, frequency is the number of squares across [0, 1]
+#[derive(Clone, Debug)]
+pub struct UvChecker {
+    pub frequency: Vec2,
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+}
+
+impl UvChecker {
+    pub const fn new(frequency: Vec2, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            frequency,
+            even,
+            odd,
+        }
+    }
+}
+
+impl Texture for UvChecker {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let cell = (context.uv * self.frequency).floor();
+        if (cell.x as i32 + cell.y as i32).rem_euclid(2) == 0 {
+            self.even.value(context)
+        } else {
+            self.odd.value(context)
+        }
+    }
+}
+
+// Lines in texture space, line_width is a fraction of a cell
+#[derive(Clone, Debug)]
+pub struct UvGrid {
+    pub frequency: Vec2,
+    pub line_width: f32,
+    pub line: Arc<dyn Texture>,
+    pub fill: Arc<dyn Texture>,
+}
+
+impl UvGrid {
+    pub const fn new(
+        frequency: Vec2,
+        line_width: f32,
+        line: Arc<dyn Texture>,
+        fill: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            frequency,
+            line_width,
+            line,
+            fill,
+        }
+    }
+}
+
+impl Texture for UvGrid {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let cell = (context.uv * self.frequency).fract_gl();
+        let half_width = 0.5 * self.line_width;
+        let on_line = |f: f32| f < half_width || f > 1.0 - half_width;
+        if on_line(cell.x) || on_line(cell.y) {
+            self.line.value(context)
+        } else {
+            self.fill.value(context)
+        }
+    }
+}
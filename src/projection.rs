@@ -0,0 +1,189 @@
+use std::{
+    f32::consts::{PI, TAU},
+    fmt::Debug,
+};
+
+use glam::{Vec2, Vec3};
+
+// Maps a film position to a ray in camera space, looking down -z with +y up
+// film: [0,1]x[0,1] from the top left of the image
+// lens: point in the unit disk, for projections with a finite aperture
+// aspect_ratio: image width over height
+pub trait Projection: Send + Sync + Debug {
+    // origin, direction and the weight of the sample, or None where the projection covers no
+    // directions. The weight is 1 unless the projection models how much light reaches the film
+    fn generate_ray(&self, film: Vec2, lens: Vec2, aspect_ratio: f32) -> Option<(Vec3, Vec3, f32)>;
+}
+
+// The book's pinhole/thin-lens camera
+#[derive(Clone, Copy, Debug)]
+pub struct PerspectiveProjection {
+    pub vfov: f32, // degrees
+    pub focus_dist: f32,
+    pub lens_radius: f32,
+}
+
+impl PerspectiveProjection {
+    pub fn new(vfov: f32) -> Self {
+        Self {
+            vfov,
+            focus_dist: 1.0,
+            lens_radius: 0.0,
+        }
+    }
+
+    // defocus_angle: degrees of the cone from each focal plane point to the lens
+    pub fn with_defocus(vfov: f32, defocus_angle: f32, focus_dist: f32) -> Self {
+        Self {
+            vfov,
+            focus_dist,
+            lens_radius: focus_dist * (defocus_angle / 2.0).to_radians().tan(),
+        }
+    }
+}
+
+impl Projection for PerspectiveProjection {
+    fn generate_ray(&self, film: Vec2, lens: Vec2, aspect_ratio: f32) -> Option<(Vec3, Vec3, f32)> {
+        let viewport_height = 2.0 * (self.vfov.to_radians() / 2.0).tan() * self.focus_dist;
+        let viewport_width = viewport_height * aspect_ratio;
+
+        let focus_point = Vec3::new(
+            (film.x - 0.5) * viewport_width,
+            (0.5 - film.y) * viewport_height,
+            -self.focus_dist,
+        );
+        let origin = (lens * self.lens_radius).extend(0.0);
+
+        Some((origin, focus_point - origin, 1.0))
+    }
+}
+
+// Parallel rays from a view rectangle around the camera center
+#[derive(Clone, Copy, Debug)]
+pub struct OrthographicProjection {
+    pub view_height: f32, // world units, the width follows the image aspect ratio
+}
+
+impl OrthographicProjection {
+    pub fn new(view_height: f32) -> Self {
+        Self { view_height }
+    }
+}
+
+impl Projection for OrthographicProjection {
+    fn generate_ray(
+        &self,
+        film: Vec2,
+        _lens: Vec2,
+        aspect_ratio: f32,
+    ) -> Option<(Vec3, Vec3, f32)> {
+        let origin = Vec3::new(
+            (film.x - 0.5) * self.view_height * aspect_ratio,
+            (0.5 - film.y) * self.view_height,
+            0.0,
+        );
+
+        Some((origin, Vec3::NEG_Z, 1.0))
+    }
+}
+
+// Equidistant fisheye: the angle from the view direction grows linearly with the distance from
+// the image center, up to fov / 2 on the circle inscribed in the image
+#[derive(Clone, Copy, Debug)]
+pub struct FisheyeProjection {
+    pub fov: f32, // degrees, may exceed 180
+}
+
+impl FisheyeProjection {
+    pub fn new(fov: f32) -> Self {
+        Self { fov }
+    }
+}
+
+impl Projection for FisheyeProjection {
+    fn generate_ray(
+        &self,
+        film: Vec2,
+        _lens: Vec2,
+        aspect_ratio: f32,
+    ) -> Option<(Vec3, Vec3, f32)> {
+        // [-1, 1] across the shorter image side
+        let mut p = 2.0 * Vec2::new(film.x - 0.5, 0.5 - film.y);
+        if aspect_ratio >= 1.0 {
+            p.x *= aspect_ratio;
+        } else {
+            p.y /= aspect_ratio;
+        }
+
+        let r = p.length();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * self.fov.to_radians() / 2.0;
+        let (sin_phi, cos_phi) = if r > 0.0 {
+            (p.y / r, p.x / r)
+        } else {
+            (0.0, 1.0)
+        };
+        let direction = Vec3::new(theta.sin() * cos_phi, theta.sin() * sin_phi, -theta.cos());
+
+        Some((Vec3::ZERO, direction, 1.0))
+    }
+}
+
+// Full 360x180 degree latitude-longitude panorama, looking forward at the image center
+// Best rendered at a 2:1 aspect ratio
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EquirectangularProjection;
+
+impl Projection for EquirectangularProjection {
+    fn generate_ray(
+        &self,
+        film: Vec2,
+        _lens: Vec2,
+        _aspect_ratio: f32,
+    ) -> Option<(Vec3, Vec3, f32)> {
+        let longitude = (film.x - 0.5) * TAU;
+        let latitude = (0.5 - film.y) * PI;
+        let direction = Vec3::new(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos(),
+        );
+
+        Some((Vec3::ZERO, direction, 1.0))
+    }
+}
+
+// Six 90 degree faces in a 3x2 grid, +x -x +y on the top row and -y +z -z below, oriented as
+// OpenGL cube map faces in camera space. Best rendered at a 3:2 aspect ratio
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CubeMapProjection;
+
+impl Projection for CubeMapProjection {
+    fn generate_ray(
+        &self,
+        film: Vec2,
+        _lens: Vec2,
+        _aspect_ratio: f32,
+    ) -> Option<(Vec3, Vec3, f32)> {
+        let column = ((film.x * 3.0) as usize).min(2);
+        let row = ((film.y * 2.0) as usize).min(1);
+
+        // [-1, 1] across the face, left to right and top to bottom
+        let sc = 2.0 * (film.x * 3.0 - column as f32) - 1.0;
+        let tc = 2.0 * (film.y * 2.0 - row as f32) - 1.0;
+
+        let direction = match row * 3 + column {
+            0 => Vec3::new(1.0, -tc, -sc),
+            1 => Vec3::new(-1.0, -tc, sc),
+            2 => Vec3::new(sc, 1.0, tc),
+            3 => Vec3::new(sc, -1.0, -tc),
+            4 => Vec3::new(sc, -tc, 1.0),
+            _ => Vec3::new(-sc, -tc, -1.0),
+        };
+
+        Some((Vec3::ZERO, direction, 1.0))
+    }
+}
@@ -6,6 +6,7 @@ use rand::{Rng, RngCore};
 use crate::{
     aabb::Aabb,
     hit::{HitRecord, Hittable},
+    hittable_list::HittableList,
     interval::Interval,
     material::Material,
     ray::Ray,
@@ -56,10 +57,29 @@ impl Quad {
         const UNIT_INTERVAL: Interval = Interval::new(0.0, 1.0);
         UNIT_INTERVAL.contains(a) && UNIT_INTERVAL.contains(b)
     }
+
+    // Invert the jacobian of the bilinear uv mapping at alpha, beta
+    // Falls back to the edges when the texture coordinates are degenerate
+    fn uv_derivatives(&self, alpha: f32, beta: f32) -> (Vec3, Vec3) {
+        let duv_dalpha =
+            (1.0 - beta) * (self.uvs[1] - self.uvs[0]) + beta * (self.uvs[3] - self.uvs[2]);
+        let duv_dbeta =
+            (1.0 - alpha) * (self.uvs[2] - self.uvs[0]) + alpha * (self.uvs[3] - self.uvs[1]);
+        let det = duv_dalpha.x * duv_dbeta.y - duv_dbeta.x * duv_dalpha.y;
+        if det.abs() < 1e-12 {
+            return (self.u, self.v);
+        }
+
+        let inv_det = 1.0 / det;
+        (
+            (duv_dbeta.y * self.u - duv_dalpha.y * self.v) * inv_det,
+            (duv_dalpha.x * self.v - duv_dbeta.x * self.u) * inv_det,
+        )
+    }
 }
 
 impl Hittable for Quad {
-    fn hit(&self, ray: Ray, ray_t: Interval, _rng: &mut dyn RngCore) -> Option<HitRecord> {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
         let denom = self.normal.dot(ray.direction);
 
         // ray is parallel to plane
@@ -91,14 +111,15 @@ impl Hittable for Quad {
             + (1.0 - alpha) * beta * self.uvs[2]
             + alpha * beta * self.uvs[3];
 
-        Some(HitRecord {
-            point: hit_point,
-            normal: self.normal,
-            material: self.material.clone(),
-            t,
-            uv,
-            front_face: denom <= 0.0,
-        })
+        let mut hit_record =
+            HitRecord::new(ray, hit_point, self.normal, self.material.clone(), t, uv);
+        (hit_record.dpdu, hit_record.dpdv) = self.uv_derivatives(alpha, beta);
+
+        if !self.material.alpha_test(&hit_record, rng) {
+            return None;
+        }
+
+        Some(hit_record)
     }
 
     fn bounding_box(&self) -> Aabb {
@@ -125,3 +146,31 @@ impl Hittable for Quad {
         p - origin
     }
 }
+
+// The box with opposite corners a and b as six quads, as in the book
+// Each face has the full [0,1] uv square
+pub fn quad_box(a: Vec3, b: Vec3, material: Arc<dyn Material>) -> HittableList {
+    let mut sides = HittableList::new();
+
+    let min = a.min(b);
+    let max = a.max(b);
+
+    let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+    let uvs = [Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ONE];
+
+    let faces = [
+        (Vec3::new(min.x, min.y, max.z), dx, dy),  // front
+        (Vec3::new(max.x, min.y, max.z), -dz, dy), // right
+        (Vec3::new(max.x, min.y, min.z), -dx, dy), // back
+        (Vec3::new(min.x, min.y, min.z), dz, dy),  // left
+        (Vec3::new(min.x, max.y, max.z), dx, -dz), // top
+        (Vec3::new(min.x, min.y, min.z), dx, dz),  // bottom
+    ];
+    for (q, u, v) in faces {
+        sides.add(Arc::new(Quad::new(q, u, v, uvs, material.clone())));
+    }
+
+    sides
+}
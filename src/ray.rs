@@ -1,14 +1,43 @@
 use glam::Vec3;
 
+// Offset rays for the neighbouring pixels in x and y, used to estimate texture footprints
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct RayDifferential {
+    pub rx_origin: Vec3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Vec3,
+    pub ry_direction: Vec3,
+}
+
 #[derive(Clone, Copy, PartialEq, Default, Debug)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    pub time: f32, // within the camera shutter interval
+    pub differential: Option<RayDifferential>,
 }
 
 impl Ray {
     pub const fn new(origin: Vec3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self::with_time(origin, direction, 0.0)
+    }
+
+    pub const fn with_time(origin: Vec3, direction: Vec3, time: f32) -> Self {
+        Self::with_differential(origin, direction, time, None)
+    }
+
+    pub const fn with_differential(
+        origin: Vec3,
+        direction: Vec3,
+        time: f32,
+        differential: Option<RayDifferential>,
+    ) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
+            differential,
+        }
     }
 
     pub fn at(self, t: f32) -> Vec3 {
@@ -0,0 +1,445 @@
+use std::{fmt::Debug, sync::Arc};
+
+use glam::{UVec3, Vec2, Vec3};
+use rand::RngCore;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sphere::Sphere,
+};
+
+// Signed distance to a surface, negative inside
+// Sphere tracing relies on the distance never overestimating the true one
+pub trait Sdf: Send + Sync + Debug {
+    fn distance(&self, point: Vec3) -> f32;
+
+    fn bounding_box(&self) -> Aabb;
+}
+
+// An Sdf rendered by sphere tracing inside its bounding box
+#[derive(Clone, Debug)]
+pub struct ImplicitSurface {
+    pub sdf: Arc<dyn Sdf>,
+    pub material: Arc<dyn Material>,
+    pub tolerance: f32, // distance counted as a hit, and a tenth of the gradient step
+    pub max_steps: u32,
+    pub step_scale: f32, // below one for fields that overestimate, like twists
+    bbox: Aabb,
+}
+
+impl ImplicitSurface {
+    pub fn new(sdf: Arc<dyn Sdf>, material: Arc<dyn Material>) -> Self {
+        let bbox = sdf.bounding_box();
+
+        Self {
+            sdf,
+            material,
+            tolerance: 1e-4,
+            max_steps: 256,
+            step_scale: 1.0,
+            bbox,
+        }
+    }
+
+    pub const fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub const fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub const fn with_step_scale(mut self, step_scale: f32) -> Self {
+        self.step_scale = step_scale;
+        self
+    }
+
+    // Normalized gradient from the tetrahedron of samples around point
+    fn normal(&self, point: Vec3) -> Vec3 {
+        let h = 10.0 * self.tolerance;
+        let offsets = [
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ];
+
+        offsets
+            .iter()
+            .map(|&offset| offset * self.sdf.distance(point + h * offset))
+            .sum::<Vec3>()
+            .try_normalize()
+            .unwrap_or(Vec3::Y)
+    }
+}
+
+impl Hittable for ImplicitSurface {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let ray_t = self.bbox.clip(ray, ray_t)?;
+        let direction_length = ray.direction.length();
+
+        // steps by the distance to the nearest surface, which can't skip past one
+        // absolute so rays starting inside march out to the surface too
+        let mut t = ray_t.min;
+        for _ in 0..self.max_steps {
+            if t >= ray_t.max {
+                return None;
+            }
+
+            let point = ray.at(t);
+            let distance = self.sdf.distance(point).abs();
+            if distance < self.tolerance {
+                let normal = self.normal(point);
+                let center = self.bbox.get_corners();
+                let uv = (point - 0.5 * (center.0 + center.1))
+                    .try_normalize()
+                    .map_or(Vec2::ZERO, Sphere::get_sphere_uv);
+
+                let hit_record = HitRecord::new(ray, point, normal, self.material.clone(), t, uv);
+                if self.material.alpha_test(&hit_record, rng) {
+                    return Some(hit_record);
+                }
+
+                // continue behind the surface
+                t += 2.0 * self.tolerance / direction_length;
+                continue;
+            }
+
+            t += self.step_scale * distance / direction_length;
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3, _rng: &mut dyn RngCore) -> f32 {
+        0.0
+    }
+
+    fn random(&self, _origin: Vec3, _rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::X
+    }
+}
+
+// Primitives, centered on the origin
+
+#[derive(Clone, Debug)]
+pub struct SphereSdf {
+    pub radius: f32,
+}
+
+impl SphereSdf {
+    pub const fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+impl Sdf for SphereSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        point.length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_corners(Vec3::splat(-self.radius), Vec3::splat(self.radius))
+    }
+}
+
+// Box with its edges and corners rounded off by radius, inside half_extents
+#[derive(Clone, Debug)]
+pub struct RoundedBoxSdf {
+    pub half_extents: Vec3,
+    pub radius: f32,
+}
+
+impl RoundedBoxSdf {
+    pub const fn new(half_extents: Vec3, radius: f32) -> Self {
+        Self {
+            half_extents,
+            radius,
+        }
+    }
+}
+
+impl Sdf for RoundedBoxSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        let q = point.abs() - self.half_extents + self.radius;
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0) - self.radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_corners(-self.half_extents, self.half_extents)
+    }
+}
+
+// Around the y axis
+#[derive(Clone, Debug)]
+pub struct TorusSdf {
+    pub major_radius: f32,
+    pub minor_radius: f32,
+}
+
+impl TorusSdf {
+    pub const fn new(major_radius: f32, minor_radius: f32) -> Self {
+        Self {
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for TorusSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        let ring = Vec2::new(point.x, point.z).length() - self.major_radius;
+        Vec2::new(ring, point.y).length() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(outer, self.minor_radius, outer);
+        Aabb::from_corners(-extent, extent)
+    }
+}
+
+// The Mandelbulb fractal, from its distance estimator
+// Fits in a radius of about 1.2 for the usual power of 8
+#[derive(Clone, Debug)]
+pub struct MandelbulbSdf {
+    pub power: f32,
+    pub iterations: u32,
+}
+
+impl MandelbulbSdf {
+    pub const fn new(power: f32, iterations: u32) -> Self {
+        Self { power, iterations }
+    }
+}
+
+impl Sdf for MandelbulbSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        const BAILOUT: f32 = 2.0;
+
+        let mut z = point;
+        let mut derivative = 1.0;
+        let mut r = z.length();
+        for _ in 0..self.iterations {
+            if r > BAILOUT || r == 0.0 {
+                break;
+            }
+
+            // z -> z^power + point in spherical coordinates
+            let theta = (z.y / r).acos() * self.power;
+            let phi = z.z.atan2(z.x) * self.power;
+            derivative = r.powf(self.power - 1.0) * self.power * derivative + 1.0;
+
+            let scaled = r.powf(self.power);
+            z = scaled
+                * Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                )
+                + point;
+            r = z.length();
+        }
+
+        if r == 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / derivative
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_corners(Vec3::splat(-1.25), Vec3::splat(1.25))
+    }
+}
+
+// Operators
+
+#[derive(Clone, Debug)]
+pub struct TranslatedSdf {
+    pub sdf: Arc<dyn Sdf>,
+    pub offset: Vec3,
+}
+
+impl TranslatedSdf {
+    pub fn new(sdf: Arc<dyn Sdf>, offset: Vec3) -> Self {
+        Self { sdf, offset }
+    }
+}
+
+impl Sdf for TranslatedSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        self.sdf.distance(point - self.offset)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.sdf.bounding_box() + self.offset
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UnionSdf {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+}
+
+impl UnionSdf {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Sdf for UnionSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        self.a.distance(point).min(self.b.distance(point))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::merged(self.a.bounding_box(), self.b.bounding_box())
+    }
+}
+
+// Union blended over a distance of about smoothness where the shapes meet
+#[derive(Clone, Debug)]
+pub struct SmoothUnionSdf {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+    pub smoothness: f32,
+}
+
+impl SmoothUnionSdf {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>, smoothness: f32) -> Self {
+        Self { a, b, smoothness }
+    }
+}
+
+impl Sdf for SmoothUnionSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        // polynomial smooth minimum, which stays below the true minimum by at most smoothness / 4
+        let (a, b) = (self.a.distance(point), self.b.distance(point));
+        let h = (self.smoothness - (a - b).abs()).max(0.0) / self.smoothness;
+        a.min(b) - h * h * self.smoothness * 0.25
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let (min, max) = Aabb::merged(self.a.bounding_box(), self.b.bounding_box()).get_corners();
+        let margin = Vec3::splat(0.25 * self.smoothness);
+        Aabb::from_corners(min - margin, max + margin)
+    }
+}
+
+// a with b carved out of it
+#[derive(Clone, Debug)]
+pub struct SubtractionSdf {
+    pub a: Arc<dyn Sdf>,
+    pub b: Arc<dyn Sdf>,
+}
+
+impl SubtractionSdf {
+    pub fn new(a: Arc<dyn Sdf>, b: Arc<dyn Sdf>) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Sdf for SubtractionSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        self.a.distance(point).max(-self.b.distance(point))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.a.bounding_box()
+    }
+}
+
+// Rotated around the y axis by rate radians per unit of height
+// Stretches distances, so trace it with a step scale below one for strong twists
+#[derive(Clone, Debug)]
+pub struct TwistSdf {
+    pub sdf: Arc<dyn Sdf>,
+    pub rate: f32,
+}
+
+impl TwistSdf {
+    pub fn new(sdf: Arc<dyn Sdf>, rate: f32) -> Self {
+        Self { sdf, rate }
+    }
+}
+
+impl Sdf for TwistSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        // untwist the point back into the shape's space
+        let (sin, cos) = (-self.rate * point.y).sin_cos();
+        let untwisted = Vec3::new(
+            cos * point.x - sin * point.z,
+            point.y,
+            sin * point.x + cos * point.z,
+        );
+        self.sdf.distance(untwisted)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // any rotation of the shape fits in the circle around its farthest corner
+        let bbox = self.sdf.bounding_box();
+        let radius = [bbox.x.min, bbox.x.max]
+            .into_iter()
+            .flat_map(|x| [bbox.z.min, bbox.z.max].map(|z| Vec2::new(x, z).length()))
+            .fold(0.0, f32::max);
+        Aabb::new(
+            Interval::new(-radius, radius),
+            bbox.y,
+            Interval::new(-radius, radius),
+        )
+    }
+}
+
+// counts copies of the shape along each axis, spacing apart and centered on the origin
+// The shape should fit in its cell, since only the nearest copy is measured
+#[derive(Clone, Debug)]
+pub struct RepetitionSdf {
+    pub sdf: Arc<dyn Sdf>,
+    pub spacing: Vec3,
+    pub counts: UVec3,
+}
+
+impl RepetitionSdf {
+    pub fn new(sdf: Arc<dyn Sdf>, spacing: Vec3, counts: UVec3) -> Self {
+        Self {
+            sdf,
+            spacing,
+            counts,
+        }
+    }
+
+    // offset of the middle of the row from the first copy, in cells
+    fn half_span(&self) -> Vec3 {
+        0.5 * (self.counts.max(UVec3::ONE) - UVec3::ONE).as_vec3()
+    }
+}
+
+impl Sdf for RepetitionSdf {
+    fn distance(&self, point: Vec3) -> f32 {
+        let half_span = self.half_span();
+        let cell = (point / self.spacing + half_span)
+            .round()
+            .clamp(Vec3::ZERO, 2.0 * half_span);
+
+        // an axis with a single copy has no spacing to divide by
+        let cell = Vec3::select(self.counts.cmpgt(UVec3::ONE), cell, half_span);
+        self.sdf.distance(point - self.spacing * (cell - half_span))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let (min, max) = self.sdf.bounding_box().get_corners();
+        let extent = self.spacing.abs() * self.half_span();
+        Aabb::from_corners(min - extent, max + extent)
+    }
+}
@@ -14,7 +14,8 @@ use crate::{
 
 #[derive(Clone, Debug)]
 pub struct Sphere {
-    pub center: Vec3,
+    pub center: Vec3, // at time 0
+    pub motion: Vec3, // center displacement from time 0 to time 1, ignored by light sampling
     pub radius: f32,
     pub material: Arc<dyn Material>,
     bbox: Aabb,
@@ -26,12 +27,29 @@ impl Sphere {
         let rvec = Vec3::splat(r);
         Self {
             center,
+            motion: Vec3::ZERO,
             radius: r,
             material,
             bbox: Aabb::from_corners(center - rvec, center + rvec),
         }
     }
 
+    // Moves linearly from center0 at time 0 to center1 at time 1, as in the book
+    pub fn moving(center0: Vec3, center1: Vec3, radius: f32, material: Arc<dyn Material>) -> Self {
+        let mut sphere = Self::new(center0, radius, material);
+        let rvec = Vec3::splat(sphere.radius);
+        sphere.motion = center1 - center0;
+        sphere.bbox = Aabb::merged(
+            sphere.bbox,
+            Aabb::from_corners(center1 - rvec, center1 + rvec),
+        );
+        sphere
+    }
+
+    pub fn center_at(&self, time: f32) -> Vec3 {
+        self.center + time * self.motion
+    }
+
     pub fn get_sphere_uv(point: Vec3) -> Vec2 {
         // point: point on sphere of radius one centered at origin
         // u: [0,1] of angle around y axis from x=-1.
@@ -42,6 +60,25 @@ impl Sphere {
         Vec2::new(phi / (2.0 * PI), theta / PI)
     }
 
+    // Derivatives of the get_sphere_uv parameterization, for a unit normal
+    // Zero at the poles where it is singular
+    fn uv_derivatives(&self, normal: Vec3) -> (Vec3, Vec3) {
+        let sin_theta = (1.0 - normal.y * normal.y).max(0.0).sqrt();
+        if sin_theta < 1e-6 {
+            return (Vec3::ZERO, Vec3::ZERO);
+        }
+
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(normal.z, 0.0, -normal.x);
+        let dpdv = PI
+            * self.radius
+            * Vec3::new(
+                -normal.x * normal.y / sin_theta,
+                sin_theta,
+                -normal.z * normal.y / sin_theta,
+            );
+        (dpdu, dpdv)
+    }
+
     fn random_to_sphere(radius: f32, distance_squared: f32, rng: &mut dyn RngCore) -> Vec3 {
         let r1 = rng.random::<f32>();
         let r2 = rng.random::<f32>();
@@ -59,8 +96,9 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: Ray, ray_t: Interval, _rng: &mut dyn RngCore) -> Option<HitRecord> {
-        let origin_center = self.center - ray.origin;
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let center = self.center_at(ray.time);
+        let origin_center = center - ray.origin;
         let a = ray.direction.length_squared();
         // let b = -2.0 * ray.direction.dot(origin_center);
         let h = ray.direction.dot(origin_center); // h = -b/2
@@ -73,34 +111,40 @@ impl Hittable for Sphere {
         }
 
         // (-b - discriminant.sqrt()) / (2.0 * a)
-        let mut root = (h - discriminant.sqrt()) / a;
-
-        if !ray_t.surrounds(root) {
-            root = (h + discriminant.sqrt()) / a;
+        // the far root is also tried when the near one is cut out by the material
+        for root in [(h - discriminant.sqrt()) / a, (h + discriminant.sqrt()) / a] {
             if !ray_t.surrounds(root) {
-                return None;
+                continue;
             }
-        }
 
-        let point = ray.at(root);
-        let outward_normal = (point - self.center) / self.radius;
+            let point = ray.at(root);
+            let outward_normal = (point - center) / self.radius;
+
+            let mut hit_record = HitRecord::new(
+                ray,
+                point,
+                outward_normal,
+                self.material.clone(),
+                root,
+                Self::get_sphere_uv(outward_normal),
+            );
+            (hit_record.dpdu, hit_record.dpdv) = self.uv_derivatives(outward_normal);
+
+            if self.material.alpha_test(&hit_record, rng) {
+                return Some(hit_record);
+            }
+        }
 
-        let mut hit_record = HitRecord {
-            point,
-            material: self.material.clone(),
-            t: root,
-            uv: Self::get_sphere_uv(outward_normal),
-            normal: Vec3::ZERO,
-            front_face: false,
-        };
-        hit_record.set_face_normal(ray, outward_normal);
-        Some(hit_record)
+        None
     }
 
     fn bounding_box(&self) -> Aabb {
         self.bbox
     }
 
+    // Light sampling has no ray time, so a moving sphere is sampled where it is at time 0
+    // As a light it should keep still, pdf_value and random disagree with hit at other times
+    // and bias its motion blur
     fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut dyn RngCore) -> f32 {
         let Some(_hit_record) = self.hit(
             Ray::new(origin, direction),
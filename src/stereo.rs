@@ -0,0 +1,229 @@
+use std::{f32::consts::TAU, path::Path, sync::Arc};
+
+use anyhow::Context;
+use glam::{Quat, Vec2, Vec3};
+
+use crate::{
+    camera::Camera,
+    hit::Hittable,
+    projection::{EquirectangularProjection, Projection},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    // -1 for the left eye, along camera right
+    fn side(self) -> f32 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Convergence {
+    // parallel eyes with asymmetric frusta meeting at the convergence distance
+    #[default]
+    OffAxis,
+    // eyes rotated towards the convergence point, with some vertical parallax at the edges
+    ToeIn,
+}
+
+// Two eyes around a camera, in scene units
+#[derive(Clone, Copy, Debug)]
+pub struct StereoRig {
+    pub interocular: f32,
+    pub convergence_distance: f32, // infinite for parallel views
+    pub convergence: Convergence,
+}
+
+impl StereoRig {
+    pub fn new(interocular: f32, convergence_distance: f32) -> Self {
+        Self {
+            interocular,
+            convergence_distance,
+            convergence: Convergence::default(),
+        }
+    }
+
+    pub fn with_convergence(mut self, convergence: Convergence) -> Self {
+        self.convergence = convergence;
+        self
+    }
+
+    // The camera moved to one eye, with its projection
+    pub fn eye_camera(&self, camera: &Camera, eye: Eye) -> Camera {
+        camera
+            .clone()
+            .with_projection(Arc::new(StereoEyeProjection {
+                center: camera.projection(),
+                eye_offset: eye.side() * self.interocular / 2.0,
+                convergence_distance: self.convergence_distance,
+                convergence: self.convergence,
+            }))
+    }
+
+    pub fn eye_cameras(&self, camera: &Camera) -> [Camera; 2] {
+        [
+            self.eye_camera(camera, Eye::Left),
+            self.eye_camera(camera, Eye::Right),
+        ]
+    }
+
+    // Omni-directional stereo panoramas for both eyes, see OdsProjection
+    pub fn ods_cameras(&self, camera: &Camera) -> [Camera; 2] {
+        [Eye::Left, Eye::Right].map(|eye| {
+            camera.clone().with_projection(Arc::new(OdsProjection {
+                eye,
+                interocular: self.interocular,
+            }))
+        })
+    }
+}
+
+// One eye of a stereo pair, from the rays of the center camera's projection
+#[derive(Clone, Debug)]
+pub struct StereoEyeProjection {
+    pub center: Arc<dyn Projection>,
+    pub eye_offset: f32, // along camera right
+    pub convergence_distance: f32,
+    pub convergence: Convergence,
+}
+
+impl Projection for StereoEyeProjection {
+    fn generate_ray(&self, film: Vec2, lens: Vec2, aspect_ratio: f32) -> Option<(Vec3, Vec3, f32)> {
+        let (origin, direction, weight) = self.center.generate_ray(film, lens, aspect_ratio)?;
+        let eye_origin = origin + Vec3::X * self.eye_offset;
+
+        match self.convergence {
+            Convergence::OffAxis => {
+                // through the point where the center ray meets the convergence plane, so both
+                // eyes agree there; rays that never get there are only moved
+                let t = (-self.convergence_distance - origin.z) / direction.z;
+                if !t.is_finite() || t <= 0.0 {
+                    return Some((eye_origin, direction, weight));
+                }
+                Some((eye_origin, origin + t * direction - eye_origin, weight))
+            }
+            Convergence::ToeIn => {
+                let angle = self.eye_offset.atan2(self.convergence_distance);
+                let rotation = Quat::from_rotation_y(angle);
+                Some((
+                    Vec3::X * self.eye_offset + rotation * origin,
+                    rotation * direction,
+                    weight,
+                ))
+            }
+        }
+    }
+}
+
+// Equirectangular panorama for one eye, where every ray starts on the circle the eyes sweep
+// turning around the camera, so each direction is seen with the right parallax
+#[derive(Clone, Copy, Debug)]
+pub struct OdsProjection {
+    pub eye: Eye,
+    pub interocular: f32,
+}
+
+impl Projection for OdsProjection {
+    fn generate_ray(&self, film: Vec2, lens: Vec2, aspect_ratio: f32) -> Option<(Vec3, Vec3, f32)> {
+        let (_, direction, weight) =
+            EquirectangularProjection.generate_ray(film, lens, aspect_ratio)?;
+
+        // camera right when turned to this longitude
+        let longitude = (film.x - 0.5) * TAU;
+        let right = Vec3::new(longitude.cos(), 0.0, longitude.sin());
+
+        Some((
+            self.eye.side() * self.interocular / 2.0 * right,
+            direction,
+            weight,
+        ))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum ViewLayout {
+    // one file per view
+    #[default]
+    Separate,
+    SideBySide,
+    TopBottom,
+}
+
+// Renders every camera against the same world, so one BVH serves all the views
+pub fn render_views(
+    world: &impl Hittable,
+    lights: Arc<dyn Hittable>,
+    cameras: &[Camera],
+) -> Vec<image::RgbImage> {
+    cameras
+        .iter()
+        .enumerate()
+        .map(|(index, camera)| {
+            eprintln!("View {}/{}", index + 1, cameras.len());
+            let mut imgbuf = image::RgbImage::new(camera.image_width(), camera.image_height());
+            camera.render_threaded(world, lights.clone(), &mut imgbuf);
+            imgbuf
+        })
+        .collect()
+}
+
+// Views next to or above each other, in order
+pub fn combine_views(views: &[image::RgbImage], layout: ViewLayout) -> image::RgbImage {
+    let (width, height) = match layout {
+        ViewLayout::SideBySide => (
+            views.iter().map(|view| view.width()).sum(),
+            views.iter().map(|view| view.height()).max().unwrap_or(0),
+        ),
+        _ => (
+            views.iter().map(|view| view.width()).max().unwrap_or(0),
+            views.iter().map(|view| view.height()).sum(),
+        ),
+    };
+
+    let mut combined = image::RgbImage::new(width, height);
+    let mut offset = 0;
+    for view in views {
+        match layout {
+            ViewLayout::SideBySide => {
+                image::imageops::replace(&mut combined, view, offset as i64, 0);
+                offset += view.width();
+            }
+            _ => {
+                image::imageops::replace(&mut combined, view, 0, offset as i64);
+                offset += view.height();
+            }
+        }
+    }
+    combined
+}
+
+// Separate views go to path with the view index appended to the file name
+pub fn save_views(
+    views: &[image::RgbImage],
+    layout: ViewLayout,
+    path: impl AsRef<Path>,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    if layout != ViewLayout::Separate {
+        return combine_views(views, layout)
+            .save(path)
+            .with_context(|| format!("Failed to save views to {path:?}."));
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    for (index, view) in views.iter().enumerate() {
+        let view_path = path.with_file_name(format!("{stem}_{index}.{extension}"));
+        view.save(&view_path)
+            .with_context(|| format!("Failed to save view {view_path:?}."))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use std::{collections::HashMap, fmt::Debug, path::Path, sync::Arc};
+
+use anyhow::Context;
+use glam::Vec3;
+
+use crate::{
+    hittable_list::HittableList,
+    material::Material,
+    mesh::{DEFAULT_SMOOTHING_ANGLE, IndexedMesh, triangle_mesh_from_faces},
+};
+
+// Stereolithography files, ASCII or binary
+// The stored facet normals are ignored, they're often missing or wrong, and normals are
+// generated from the smoothing angle instead
+
+pub fn load_stl_meshes(
+    path: impl AsRef<Path> + Debug,
+    material: Arc<dyn Material>,
+) -> anyhow::Result<Vec<HittableList>> {
+    load_stl_meshes_with_smoothing(path, material, DEFAULT_SMOOTHING_ANGLE)
+}
+
+// smoothing_angle is in degrees, 0 for flat shading
+// Each solid of an ASCII file is its own TriangleMesh in its own list, a binary file is a
+// single one
+pub fn load_stl_meshes_with_smoothing(
+    path: impl AsRef<Path> + Debug,
+    material: Arc<dyn Material>,
+    smoothing_angle: f32,
+) -> anyhow::Result<Vec<HittableList>> {
+    let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {path:?}."))?;
+    let solids = if is_binary_stl(&bytes) {
+        parse_binary_stl(&bytes).map(|triangles| vec![triangles])
+    } else {
+        parse_ascii_stl(&bytes)
+    }
+    .with_context(|| format!("Failed to parse STL file {path:?}."))?;
+
+    Ok(solids
+        .iter()
+        .map(|triangles| {
+            eprintln!(
+                "Loading STL mesh {path:?} with {} triangles...",
+                triangles.len()
+            );
+            let (positions, faces) = weld_vertices(triangles);
+            let mut list = HittableList::new();
+            let indexed_mesh = IndexedMesh {
+                positions,
+                faces,
+                ..Default::default()
+            };
+            list.add(Arc::new(triangle_mesh_from_faces(
+                indexed_mesh,
+                smoothing_angle,
+                vec![material.clone()],
+            )));
+            list
+        })
+        .collect())
+}
+
+// Binary files can start with "solid" too, so ASCII also needs a facet on the next line, and a
+// file big enough for its triangle count is binary either way
+// Some exporters pad binary files, so the size is only a lower bound
+fn is_binary_stl(bytes: &[u8]) -> bool {
+    if binary_stl_count(bytes).is_some_and(|count| bytes.len() >= 84 + 50 * count) {
+        return true;
+    }
+
+    let mut lines = bytes
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty());
+    let looks_ascii = lines.next().is_some_and(|line| line.starts_with(b"solid"))
+        && lines
+            .next()
+            .is_some_and(|line| line.starts_with(b"facet") || line.starts_with(b"endsolid"));
+    !looks_ascii
+}
+
+fn binary_stl_count(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(80..84)?;
+    Some(u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
+}
+
+// 80 byte header, triangle count, then per triangle a normal, three vertices and two attribute
+// bytes, with anything after the last triangle ignored
+fn parse_binary_stl(bytes: &[u8]) -> anyhow::Result<Vec<[Vec3; 3]>> {
+    let count = binary_stl_count(bytes).context("Binary STL file without a header.")?;
+    let triangles = bytes
+        .get(84..84 + 50 * count)
+        .with_context(|| format!("Binary STL file too short for its {count} triangles."))?;
+
+    let read_vec3 = |bytes: &[u8]| {
+        Vec3::from_array(std::array::from_fn(|i| {
+            f32::from_le_bytes([
+                bytes[4 * i],
+                bytes[4 * i + 1],
+                bytes[4 * i + 2],
+                bytes[4 * i + 3],
+            ])
+        }))
+    };
+    Ok(triangles
+        .chunks_exact(50)
+        .map(|facet| std::array::from_fn(|i| read_vec3(&facet[12 * (i + 1)..])))
+        .collect())
+}
+
+fn parse_ascii_stl(bytes: &[u8]) -> anyhow::Result<Vec<Vec<[Vec3; 3]>>> {
+    let text = std::str::from_utf8(bytes).context("Neither binary STL nor valid UTF-8.")?;
+
+    let mut solids = Vec::new();
+    let mut triangles = Vec::new();
+    let mut vertices = Vec::with_capacity(3);
+    for (line_number, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["vertex", x, y, z] => {
+                let parse = |word: &str| {
+                    word.parse::<f32>()
+                        .with_context(|| format!("Invalid vertex on line {}.", line_number + 1))
+                };
+                vertices.push(Vec3::new(parse(x)?, parse(y)?, parse(z)?));
+            }
+            ["endloop"] => {
+                // polygons with more vertices are split into fans
+                for i in 1..vertices.len().saturating_sub(1) {
+                    triangles.push([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+                vertices.clear();
+            }
+            ["endsolid", ..] => solids.push(std::mem::take(&mut triangles)),
+            _ => {}
+        }
+    }
+    // tolerate a missing endsolid
+    if !triangles.is_empty() {
+        solids.push(triangles);
+    }
+    anyhow::ensure!(!solids.is_empty(), "No solids found.");
+    Ok(solids)
+}
+
+// STL repeats every vertex in each triangle using it, identical positions become one index
+fn weld_vertices(triangles: &[[Vec3; 3]]) -> (Vec<Vec3>, Vec<[usize; 3]>) {
+    let mut positions = Vec::new();
+    let mut indices: HashMap<[u32; 3], usize> = HashMap::new();
+    let faces = triangles
+        .iter()
+        .map(|triangle| {
+            triangle.map(|vertex| {
+                *indices
+                    .entry(vertex.to_array().map(f32::to_bits))
+                    .or_insert_with(|| {
+                        positions.push(vertex);
+                        positions.len() - 1
+                    })
+            })
+        })
+        .collect();
+    (positions, faces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLES: [[Vec3; 3]; 2] = [
+        [Vec3::ZERO, Vec3::X, Vec3::Y],
+        [Vec3::X, Vec3::new(1.0, 1.0, 0.0), Vec3::Y],
+    ];
+
+    // header starting with "solid" as some exporters write it, then padding bytes
+    fn binary(padding: usize) -> Vec<u8> {
+        let mut bytes = b"solid exported".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend((TRIANGLES.len() as u32).to_le_bytes());
+        for triangle in TRIANGLES {
+            for vector in [Vec3::Z].iter().chain(&triangle) {
+                for value in vector.to_array() {
+                    bytes.extend(value.to_le_bytes());
+                }
+            }
+            bytes.extend([0, 0]);
+        }
+        bytes.extend(std::iter::repeat_n(0, padding));
+        bytes
+    }
+
+    #[test]
+    fn ascii() {
+        let stl = "solid first
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 1 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid first
+solid second
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 1
+      vertex 1 0 1
+      vertex 0 1 1
+    endloop
+  endfacet
+endsolid second
+";
+        assert!(!is_binary_stl(stl.as_bytes()));
+        let solids = parse_ascii_stl(stl.as_bytes()).unwrap();
+        assert_eq!(solids.len(), 2);
+        assert_eq!(solids[0], TRIANGLES);
+        assert_eq!(solids[1][0][2], Vec3::new(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn binary_with_solid_header() {
+        let bytes = binary(0);
+        assert!(is_binary_stl(&bytes));
+        assert_eq!(parse_binary_stl(&bytes).unwrap(), TRIANGLES);
+    }
+
+    #[test]
+    fn padded_binary() {
+        let bytes = binary(100);
+        assert!(is_binary_stl(&bytes));
+        assert_eq!(parse_binary_stl(&bytes).unwrap(), TRIANGLES);
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let mut bytes = binary(0);
+        bytes.truncate(bytes.len() - 10);
+        assert!(parse_binary_stl(&bytes).is_err());
+    }
+
+    #[test]
+    fn welds_shared_vertices() {
+        let (positions, faces) = weld_vertices(&TRIANGLES);
+        assert_eq!(positions.len(), 4);
+        assert_eq!(faces, vec![[0, 1, 2], [1, 3, 2]]);
+    }
+}
@@ -2,10 +2,54 @@ use std::{fmt::Debug, sync::Arc};
 
 use glam::{Vec2, Vec3};
 
-use crate::interval::Interval;
+use crate::{
+    color::{luminance, srgb_to_linear},
+    hit::HitRecord,
+};
+
+// Where a texture is looked up, with the screen space derivatives of the lookup when known
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct TextureContext {
+    pub uv: Vec2,
+    pub point: Vec3,
+    pub normal: Vec3, // shading normal, zero if unknown
+    pub duvdx: Vec2,
+    pub duvdy: Vec2,
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub vertex_color: Option<Vec3>,
+}
+
+impl TextureContext {
+    pub fn new(uv: Vec2, point: Vec3) -> Self {
+        Self {
+            uv,
+            point,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_hit(hit_record: &HitRecord) -> Self {
+        Self {
+            uv: hit_record.uv,
+            point: hit_record.point,
+            normal: hit_record.normal,
+            duvdx: hit_record.duvdx,
+            duvdy: hit_record.duvdy,
+            dpdx: hit_record.dpdx,
+            dpdy: hit_record.dpdy,
+            vertex_color: hit_record.vertex_color,
+        }
+    }
+}
 
 pub trait Texture: Send + Sync + Debug {
-    fn value(&self, uv: Vec2, point: Vec3) -> Vec3;
+    fn value(&self, context: &TextureContext) -> Vec3;
+
+    // Single channel output for parameters such as roughness or masks
+    fn scalar_value(&self, context: &TextureContext) -> f32 {
+        luminance(self.value(context))
+    }
 }
 
 #[derive(Debug)]
@@ -21,10 +65,14 @@ impl SolidColor {
     pub fn from_rgb(r: f32, g: f32, b: f32) -> Self {
         Self::new(Vec3::new(r, g, b))
     }
+
+    pub const fn splat(value: f32) -> Self {
+        Self::new(Vec3::splat(value))
+    }
 }
 
 impl Texture for SolidColor {
-    fn value(&self, _uv: Vec2, _point: Vec3) -> Vec3 {
+    fn value(&self, _context: &TextureContext) -> Vec3 {
         self.albedo
     }
 }
@@ -47,48 +95,405 @@ impl SpatialChecker {
 }
 
 impl Texture for SpatialChecker {
-    fn value(&self, uv: Vec2, point: Vec3) -> Vec3 {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let point = context.point;
         let x_int = (point.x * self.scale_inv).floor() as i32;
         let y_int = (point.y * self.scale_inv).floor() as i32;
         let z_int = (point.z * self.scale_inv).floor() as i32;
 
         return if (x_int + y_int + z_int) % 2 == 0 {
-            self.even.value(uv, point)
+            self.even.value(context)
         } else {
-            self.odd.value(uv, point)
+            self.odd.value(context)
+        };
+    }
+}
+
+// The interpolated vertex color of the hit, fallback where the surface has none
+#[derive(Debug)]
+pub struct VertexColorTexture {
+    pub fallback: Arc<dyn Texture>,
+}
+
+impl VertexColorTexture {
+    pub const fn new(fallback: Arc<dyn Texture>) -> Self {
+        Self { fallback }
+    }
+}
+
+impl Default for VertexColorTexture {
+    fn default() -> Self {
+        Self::new(Arc::new(SolidColor::splat(1.0)))
+    }
+}
+
+impl Texture for VertexColorTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        match context.vertex_color {
+            Some(color) => color,
+            None => self.fallback.value(context),
+        }
+    }
+}
+
+// Encoding of the values stored in an image file
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+// What an image texture is used for, colors are sRGB encoded while data maps such as roughness
+// or normals are stored as is
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TextureUsage {
+    Color,
+    Data,
+    Alpha, // opacity, from the alpha channel when the image has one
+}
+
+impl TextureUsage {
+    pub const fn color_space(self) -> ColorSpace {
+        match self {
+            TextureUsage::Color => ColorSpace::Srgb,
+            TextureUsage::Data | TextureUsage::Alpha => ColorSpace::Linear,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl WrapMode {
+    fn wrap(self, x: i64, size: u32) -> u32 {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => x.rem_euclid(size),
+            WrapMode::Mirror => {
+                let x = x.rem_euclid(2 * size);
+                if x < size { x } else { 2 * size - 1 - x }
+            }
+            WrapMode::Clamp => x.clamp(0, size - 1),
         };
+        wrapped as u32
     }
 }
 
+// Trilinear and Anisotropic need a footprint (see ImageTexture::filtered_value) and fall back to
+// Bilinear without one
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    #[default]
+    Trilinear,
+    Anisotropic,
+}
+
 #[derive(Debug)]
 pub struct ImageTexture {
-    image: image::Rgb32FImage,
+    mip_levels: Vec<image::Rgb32FImage>, // full resolution first, down to 1x1
+    color_space: ColorSpace,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+    pub filter: TextureFilter,
+    pub uv_scale: Vec2,
+    pub uv_offset: Vec2,
 }
 
 impl ImageTexture {
-    pub const fn new(image: image::Rgb32FImage) -> Self {
-        Self { image }
+    const MAX_ANISOTROPY: f32 = 8.0;
+
+    // image holds linear values
+    pub fn new(image: image::Rgb32FImage) -> Self {
+        Self {
+            mip_levels: Self::build_mip_levels(image),
+            color_space: ColorSpace::Linear,
+            wrap_u: WrapMode::default(),
+            wrap_v: WrapMode::default(),
+            filter: TextureFilter::default(),
+            uv_scale: Vec2::ONE,
+            uv_offset: Vec2::ZERO,
+        }
     }
 
+    // Loads a color texture, see load_with_color_space
     pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
-        Ok(Self::new(
-            image::ImageReader::open(path)?
-                .with_guessed_format()?
-                .decode()?
-                .into_rgb32f(),
-        ))
+        Self::load_with_color_space(path, ColorSpace::Srgb)
     }
-}
 
-impl Texture for ImageTexture {
-    fn value(&self, mut uv: Vec2, _point: Vec3) -> Vec3 {
-        uv.x = Interval::new(0.0, 0.999).clamp(uv.x);
-        uv.y = 1.0 - Interval::new(0.001, 1.0).clamp(uv.y);
+    pub fn load_with_color_space(
+        path: impl AsRef<std::path::Path>,
+        color_space: ColorSpace,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::from_image(Self::decode(path)?, color_space))
+    }
+
+    pub fn load_for_usage(
+        path: impl AsRef<std::path::Path>,
+        usage: TextureUsage,
+    ) -> anyhow::Result<Self> {
+        Ok(Self::from_image_for_usage(Self::decode(path)?, usage))
+    }
+
+    pub fn load_alpha(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        Ok(Self::alpha_from_image(Self::decode(path)?))
+    }
 
-        let x = (uv.x * self.image.width() as f32) as u32;
-        let y = (uv.y * self.image.height() as f32) as u32;
+    fn decode(path: impl AsRef<std::path::Path>) -> anyhow::Result<image::DynamicImage> {
+        Ok(image::ImageReader::open(path)?
+            .with_guessed_format()?
+            .decode()?)
+    }
+
+    // Decodes 8 and 16 bit images from color_space to linear once, up front
+    // Float images (.hdr, .exr) are linear by convention and keep their full range
+    pub fn from_image(image: image::DynamicImage, color_space: ColorSpace) -> Self {
+        let is_float = matches!(
+            image,
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+        );
+        let color_space = if is_float {
+            ColorSpace::Linear
+        } else {
+            color_space
+        };
 
-        let pixel = self.image.get_pixel(x, y);
-        Vec3::new(pixel[0], pixel[1], pixel[2])
+        let mut image = image.into_rgb32f();
+        if color_space == ColorSpace::Srgb {
+            for value in image.iter_mut() {
+                *value = srgb_to_linear(*value);
+            }
+        }
+
+        let mut texture = Self::new(image);
+        texture.color_space = color_space;
+        texture
+    }
+
+    pub fn from_image_for_usage(image: image::DynamicImage, usage: TextureUsage) -> Self {
+        match usage {
+            TextureUsage::Alpha => Self::alpha_from_image(image),
+            _ => Self::from_image(image, usage.color_space()),
+        }
+    }
+
+    // Opacity mask, the alpha channel of images that have one and the gray values of others
+    pub fn alpha_from_image(image: image::DynamicImage) -> Self {
+        if !image.color().has_alpha() {
+            return Self::new(image.into_rgb32f());
+        }
+
+        let image = image.into_rgba32f();
+        let alpha = image::Rgb32FImage::from_fn(image.width(), image.height(), |x, y| {
+            image::Rgb([image.get_pixel(x, y).0[3]; 3])
+        });
+        Self::new(alpha)
+    }
+
+    // The color space the texels were decoded from, they are always stored linear
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn average(&self) -> Vec3 {
+        let smallest = &self.mip_levels[self.mip_levels.len() - 1];
+        Vec3::from(smallest.get_pixel(0, 0).0)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.mip_levels[0].width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.mip_levels[0].height()
+    }
+
+    // Box filtered pyramid halving each side, rounding down
+    // With odd sizes each output texel covers one and a half input texels, so no row or column
+    // is dropped and every level keeps the average of the image
+    fn build_mip_levels(image: image::Rgb32FImage) -> Vec<image::Rgb32FImage> {
+        let mut levels = vec![image];
+        loop {
+            let previous = &levels[levels.len() - 1];
+            let (width, height) = previous.dimensions();
+            if width <= 1 && height <= 1 {
+                break;
+            }
+
+            let next_width = (width / 2).max(1);
+            let next_height = (height / 2).max(1);
+            let next = image::Rgb32FImage::from_fn(next_width, next_height, |x, y| {
+                let mut sum = Vec3::ZERO;
+                for (source_y, weight_y) in Self::footprint(y, height, next_height) {
+                    for (source_x, weight_x) in Self::footprint(x, width, next_width) {
+                        sum += weight_x
+                            * weight_y
+                            * Vec3::from(previous.get_pixel(source_x, source_y).0);
+                    }
+                }
+                image::Rgb(sum.to_array())
+            });
+            levels.push(next);
+        }
+        levels
+    }
+
+    // Input texels under an output texel along one axis, with weights summing to one
+    fn footprint(index: u32, size: u32, next_size: u32) -> impl Iterator<Item = (u32, f32)> {
+        let scale = size as f32 / next_size as f32;
+        let start = index as f32 * scale;
+        let end = (index + 1) as f32 * scale;
+
+        (start.floor() as u32..(end.ceil() as u32).min(size)).map(move |source| {
+            let covered = end.min(source as f32 + 1.0) - start.max(source as f32);
+            (source, covered / scale)
+        })
+    }
+
+    fn texel(&self, level: usize, x: i64, y: i64) -> Vec3 {
+        let image = &self.mip_levels[level];
+        let x = self.wrap_u.wrap(x, image.width());
+        let y = self.wrap_v.wrap(y, image.height());
+        Vec3::from(image.get_pixel(x, y).0)
+    }
+
+    // Continuous texel coordinates of uv at a level, v points up
+    fn texel_coords(&self, level: usize, uv: Vec2) -> Vec2 {
+        let image = &self.mip_levels[level];
+        Vec2::new(
+            uv.x * image.width() as f32,
+            (1.0 - uv.y) * image.height() as f32,
+        )
+    }
+
+    fn nearest(&self, level: usize, uv: Vec2) -> Vec3 {
+        let st = self.texel_coords(level, uv).floor();
+        self.texel(level, st.x as i64, st.y as i64)
+    }
+
+    fn bilinear(&self, level: usize, uv: Vec2) -> Vec3 {
+        let st = self.texel_coords(level, uv) - 0.5;
+        let base = st.floor();
+        let f = st - base;
+        let (x, y) = (base.x as i64, base.y as i64);
+
+        let top = self
+            .texel(level, x, y)
+            .lerp(self.texel(level, x + 1, y), f.x);
+        let bottom = self
+            .texel(level, x, y + 1)
+            .lerp(self.texel(level, x + 1, y + 1), f.x);
+        top.lerp(bottom, f.y)
+    }
+
+    // Catmull-Rom, clamped to stay non-negative since it overshoots at edges
+    fn bicubic(&self, level: usize, uv: Vec2) -> Vec3 {
+        fn weights(t: f32) -> [f32; 4] {
+            let t2 = t * t;
+            let t3 = t2 * t;
+            [
+                0.5 * (-t3 + 2.0 * t2 - t),
+                0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+                0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+                0.5 * (t3 - t2),
+            ]
+        }
+
+        let st = self.texel_coords(level, uv) - 0.5;
+        let base = st.floor();
+        let f = st - base;
+        let (x, y) = (base.x as i64, base.y as i64);
+        let wx = weights(f.x);
+        let wy = weights(f.y);
+
+        let mut sum = Vec3::ZERO;
+        for (j, wy) in wy.iter().enumerate() {
+            for (i, wx) in wx.iter().enumerate() {
+                sum += wx * wy * self.texel(level, x + i as i64 - 1, y + j as i64 - 1);
+            }
+        }
+        sum.max(Vec3::ZERO)
+    }
+
+    // Bilinear lookups on the two levels around a fractional level of detail
+    fn trilinear(&self, uv: Vec2, level: f32) -> Vec3 {
+        let max_level = (self.mip_levels.len() - 1) as f32;
+        let level = level.clamp(0.0, max_level);
+        let lower = level.floor();
+        let f = level - lower;
+
+        let lower_value = self.bilinear(lower as usize, uv);
+        if f == 0.0 {
+            return lower_value;
+        }
+        lower_value.lerp(self.bilinear(lower as usize + 1, uv), f)
+    }
+
+    // Level of detail where a footprint of width (in level 0 texels) is one texel wide
+    fn level_of_detail(width: f32) -> f32 {
+        width.max(1e-8).log2()
+    }
+
+    // Filtered lookup over the pixel footprint, given the uv derivatives along the screen axes
+    pub fn filtered_value(&self, uv: Vec2, duv_dx: Vec2, duv_dy: Vec2) -> Vec3 {
+        let uv = uv * self.uv_scale + self.uv_offset;
+        let size = Vec2::new(self.width() as f32, self.height() as f32);
+        let dst_dx = duv_dx * self.uv_scale * size;
+        let dst_dy = duv_dy * self.uv_scale * size;
+
+        match self.filter {
+            TextureFilter::Trilinear => {
+                let width = dst_dx.length().max(dst_dy.length());
+                self.trilinear(uv, Self::level_of_detail(width))
+            }
+            TextureFilter::Anisotropic => {
+                // trilinear probes along the major axis, sized for the minor axis
+                let (major, mut minor, major_duv) = if dst_dx.length() >= dst_dy.length() {
+                    (dst_dx.length(), dst_dy.length(), duv_dx * self.uv_scale)
+                } else {
+                    (dst_dy.length(), dst_dx.length(), duv_dy * self.uv_scale)
+                };
+                if minor * Self::MAX_ANISOTROPY < major {
+                    minor = major / Self::MAX_ANISOTROPY;
+                }
+                if minor <= 0.0 {
+                    return self.trilinear(uv, 0.0);
+                }
+
+                let level = Self::level_of_detail(minor);
+                let probes = (major / minor).ceil().max(1.0) as usize;
+                let mut sum = Vec3::ZERO;
+                for i in 0..probes {
+                    let offset = (i as f32 + 0.5) / probes as f32 - 0.5;
+                    sum += self.trilinear(uv + offset * major_duv, level);
+                }
+                sum / probes as f32
+            }
+            _ => self.sample(uv),
+        }
+    }
+
+    // Unfiltered by footprint, uv is already transformed
+    fn sample(&self, uv: Vec2) -> Vec3 {
+        match self.filter {
+            TextureFilter::Nearest => self.nearest(0, uv),
+            TextureFilter::Bicubic => self.bicubic(0, uv),
+            TextureFilter::Bilinear | TextureFilter::Trilinear | TextureFilter::Anisotropic => {
+                self.bilinear(0, uv)
+            }
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        self.filtered_value(context.uv, context.duvdx, context.duvdy)
     }
 }
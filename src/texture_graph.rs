@@ -0,0 +1,378 @@
+use std::sync::Arc;
+
+use glam::{Mat2, Vec2, Vec3};
+
+use crate::{
+    interval::Interval,
+    texture::{SolidColor, Texture, TextureContext},
+};
+
+// Combinator textures, each takes other textures as inputs so small graphs can drive any
+// material parameter
+
+// Blends a to b by the scalar output of factor, which can be a constant or a mask
+#[derive(Clone, Debug)]
+pub struct MixTexture {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+    pub factor: Arc<dyn Texture>,
+}
+
+impl MixTexture {
+    pub const fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>, factor: Arc<dyn Texture>) -> Self {
+        Self { a, b, factor }
+    }
+
+    pub fn with_factor(a: Arc<dyn Texture>, b: Arc<dyn Texture>, factor: f32) -> Self {
+        Self::new(a, b, Arc::new(SolidColor::splat(factor)))
+    }
+}
+
+impl Texture for MixTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let factor = self.factor.scalar_value(context).clamp(0.0, 1.0);
+        self.a.value(context).lerp(self.b.value(context), factor)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MathOperation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide, // zero where b is zero
+    Minimum,
+    Maximum,
+}
+
+// Per channel arithmetic on two inputs
+#[derive(Clone, Debug)]
+pub struct MathTexture {
+    pub operation: MathOperation,
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+}
+
+impl MathTexture {
+    pub const fn new(operation: MathOperation, a: Arc<dyn Texture>, b: Arc<dyn Texture>) -> Self {
+        Self { operation, a, b }
+    }
+
+    fn apply(&self, a: Vec3, b: Vec3) -> Vec3 {
+        match self.operation {
+            MathOperation::Add => a + b,
+            MathOperation::Subtract => a - b,
+            MathOperation::Multiply => a * b,
+            MathOperation::Divide => Vec3::select(b.cmpeq(Vec3::ZERO), Vec3::ZERO, a / b),
+            MathOperation::Minimum => a.min(b),
+            MathOperation::Maximum => a.max(b),
+        }
+    }
+}
+
+impl Texture for MathTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        self.apply(self.a.value(context), self.b.value(context))
+    }
+
+    fn scalar_value(&self, context: &TextureContext) -> f32 {
+        let a = Vec3::splat(self.a.scalar_value(context));
+        let b = Vec3::splat(self.b.scalar_value(context));
+        self.apply(a, b).x
+    }
+}
+
+// Linearly maps the scalar output of input from one range to another
+#[derive(Clone, Debug)]
+pub struct RemapTexture {
+    pub input: Arc<dyn Texture>,
+    pub from: Interval,
+    pub to: Interval,
+    pub clamp: bool,
+}
+
+impl RemapTexture {
+    pub const fn new(input: Arc<dyn Texture>, from: Interval, to: Interval) -> Self {
+        Self {
+            input,
+            from,
+            to,
+            clamp: true,
+        }
+    }
+}
+
+impl Texture for RemapTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        Vec3::splat(self.scalar_value(context))
+    }
+
+    fn scalar_value(&self, context: &TextureContext) -> f32 {
+        let mut t = (self.input.scalar_value(context) - self.from.min) / self.from.size();
+        if !t.is_finite() {
+            t = 0.0;
+        }
+        if self.clamp {
+            t = t.clamp(0.0, 1.0);
+        }
+        self.to.min + t * self.to.size()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum RampInterpolation {
+    #[default]
+    Linear,
+    Constant,
+}
+
+// Maps the scalar output of input through a gradient of color stops
+#[derive(Clone, Debug)]
+pub struct ColorRamp {
+    pub input: Arc<dyn Texture>,
+    stops: Vec<(f32, Vec3)>, // sorted by position
+    pub interpolation: RampInterpolation,
+}
+
+impl ColorRamp {
+    pub fn new(input: Arc<dyn Texture>, mut stops: Vec<(f32, Vec3)>) -> Self {
+        assert!(!stops.is_empty(), "a color ramp needs at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self {
+            input,
+            stops,
+            interpolation: RampInterpolation::default(),
+        }
+    }
+
+    pub fn stops(&self) -> &[(f32, Vec3)] {
+        &self.stops
+    }
+
+    fn evaluate(&self, t: f32) -> Vec3 {
+        let next = self.stops.partition_point(|(position, _)| *position <= t);
+        if next == 0 {
+            return self.stops[0].1;
+        }
+        let (position, color) = self.stops[next - 1];
+        let Some(&(next_position, next_color)) = self.stops.get(next) else {
+            return color;
+        };
+        match self.interpolation {
+            RampInterpolation::Constant => color,
+            RampInterpolation::Linear => {
+                color.lerp(next_color, (t - position) / (next_position - position))
+            }
+        }
+    }
+}
+
+impl Texture for ColorRamp {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        self.evaluate(self.input.scalar_value(context))
+    }
+}
+
+// Shifts hue by hue_shift turns and scales saturation and value
+#[derive(Clone, Debug)]
+pub struct HueSaturation {
+    pub input: Arc<dyn Texture>,
+    pub hue_shift: f32,
+    pub saturation: f32,
+    pub value: f32,
+}
+
+impl HueSaturation {
+    pub const fn new(input: Arc<dyn Texture>, hue_shift: f32, saturation: f32, value: f32) -> Self {
+        Self {
+            input,
+            hue_shift,
+            saturation,
+            value,
+        }
+    }
+}
+
+impl Texture for HueSaturation {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        let hsv = rgb_to_hsv(self.input.value(context).max(Vec3::ZERO));
+        hsv_to_rgb(Vec3::new(
+            (hsv.x + self.hue_shift).rem_euclid(1.0),
+            (hsv.y * self.saturation).clamp(0.0, 1.0),
+            hsv.z * self.value,
+        ))
+    }
+}
+
+// Hue in turns, saturation and value in [0, 1]
+fn rgb_to_hsv(rgb: Vec3) -> Vec3 {
+    let max = rgb.max_element();
+    let min = rgb.min_element();
+    let delta = max - min;
+    if max <= 0.0 {
+        return Vec3::ZERO;
+    }
+    if delta <= 0.0 {
+        return Vec3::new(0.0, 0.0, max);
+    }
+
+    let hue = if max == rgb.x {
+        (rgb.y - rgb.z) / delta
+    } else if max == rgb.y {
+        2.0 + (rgb.z - rgb.x) / delta
+    } else {
+        4.0 + (rgb.x - rgb.y) / delta
+    };
+    Vec3::new((hue / 6.0).rem_euclid(1.0), delta / max, max)
+}
+
+fn hsv_to_rgb(hsv: Vec3) -> Vec3 {
+    let (hue, saturation, value) = (hsv.x * 6.0, hsv.y, hsv.z);
+    let channel = |n: f32| {
+        let k = (n + hue).rem_euclid(6.0);
+        value - value * saturation * k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+    Vec3::new(channel(5.0), channel(3.0), channel(1.0))
+}
+
+// Scales, rotates (radians) and then offsets the texture coordinates seen by input
+#[derive(Clone, Debug)]
+pub struct UvTransform {
+    pub input: Arc<dyn Texture>,
+    pub scale: Vec2,
+    pub rotation: f32,
+    pub offset: Vec2,
+}
+
+impl UvTransform {
+    pub const fn new(input: Arc<dyn Texture>, scale: Vec2, rotation: f32, offset: Vec2) -> Self {
+        Self {
+            input,
+            scale,
+            rotation,
+            offset,
+        }
+    }
+
+    fn transformed(&self, context: &TextureContext) -> TextureContext {
+        let linear = Mat2::from_angle(self.rotation) * Mat2::from_diagonal(self.scale);
+        TextureContext {
+            uv: linear * context.uv + self.offset,
+            duvdx: linear * context.duvdx,
+            duvdy: linear * context.duvdy,
+            ..*context
+        }
+    }
+}
+
+impl Texture for UvTransform {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        self.input.value(&self.transformed(context))
+    }
+
+    fn scalar_value(&self, context: &TextureContext) -> f32 {
+        self.input.scalar_value(&self.transformed(context))
+    }
+}
+
+// Projects input along the three axes and blends the projections by the normal, for surfaces
+// without usable texture coordinates
+#[derive(Clone, Debug)]
+pub struct Triplanar {
+    pub input: Arc<dyn Texture>,
+    pub scale: f32,
+    pub sharpness: f32, // higher values narrow the blend between projections
+}
+
+impl Triplanar {
+    pub const fn new(input: Arc<dyn Texture>, scale: f32) -> Self {
+        Self {
+            input,
+            scale,
+            sharpness: 4.0,
+        }
+    }
+
+    fn blend<T>(
+        &self,
+        context: &TextureContext,
+        lookup: impl Fn(&TextureContext) -> T,
+        zero: T,
+    ) -> T
+    where
+        T: std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+    {
+        let mut weights = context.normal.abs().powf(self.sharpness);
+        let total = weights.element_sum();
+        weights = if total > 0.0 {
+            weights / total
+        } else {
+            Vec3::Y
+        };
+
+        let project = |p: Vec3, axis: usize| match axis {
+            0 => Vec2::new(p.z, p.y),
+            1 => Vec2::new(p.x, p.z),
+            _ => Vec2::new(p.x, p.y),
+        };
+        let mut sum = zero;
+        for axis in 0..3 {
+            if weights[axis] == 0.0 {
+                continue;
+            }
+            let projected = TextureContext {
+                uv: self.scale * project(context.point, axis),
+                duvdx: self.scale * project(context.dpdx, axis),
+                duvdy: self.scale * project(context.dpdy, axis),
+                ..*context
+            };
+            sum = sum + lookup(&projected) * weights[axis];
+        }
+        sum
+    }
+}
+
+impl Texture for Triplanar {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        self.blend(context, |context| self.input.value(context), Vec3::ZERO)
+    }
+
+    fn scalar_value(&self, context: &TextureContext) -> f32 {
+        self.blend(context, |context| self.input.scalar_value(context), 0.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+}
+
+// One channel of input as a scalar, for packed maps such as occlusion-roughness-metallic
+#[derive(Clone, Debug)]
+pub struct ChannelTexture {
+    pub input: Arc<dyn Texture>,
+    pub channel: Channel,
+}
+
+impl ChannelTexture {
+    pub const fn new(input: Arc<dyn Texture>, channel: Channel) -> Self {
+        Self { input, channel }
+    }
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, context: &TextureContext) -> Vec3 {
+        Vec3::splat(self.scalar_value(context))
+    }
+
+    fn scalar_value(&self, context: &TextureContext) -> f32 {
+        let color = self.input.value(context);
+        match self.channel {
+            Channel::Red => color.x,
+            Channel::Green => color.y,
+            Channel::Blue => color.z,
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use glam::{Vec2, Vec3};
+use rand::{Rng, RngCore};
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable, uniform_area_pdf_value},
+    interval::Interval,
+    material::Material,
+    onb::Onb,
+    ray::Ray,
+};
+
+#[derive(Clone, Debug)]
+pub struct Torus {
+    pub center: Vec3,
+    pub major_radius: f32, // from the center to the middle of the tube
+    pub minor_radius: f32, // of the tube
+    pub material: Arc<dyn Material>,
+    frame: Onb, // w along the axis of symmetry
+    bbox: Aabb,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f32,
+        minor_radius: f32,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let frame = Onb::new(axis);
+        let outer = major_radius + minor_radius;
+
+        let mut bbox = Aabb::EMPTY;
+        for corner in [-1.0, 1.0].into_iter().flat_map(|x| {
+            [-1.0, 1.0]
+                .into_iter()
+                .flat_map(move |y| [-1.0, 1.0].map(|z| Vec3::new(x, y, z)))
+        }) {
+            let point = center + frame.transform(corner * Vec3::new(outer, outer, minor_radius));
+            bbox.merge(Aabb::from_corners(point, point));
+        }
+
+        Self {
+            center,
+            major_radius,
+            minor_radius,
+            material,
+            frame,
+            bbox,
+        }
+    }
+
+    pub fn area(&self) -> f32 {
+        4.0 * PI * PI * self.major_radius * self.minor_radius
+    }
+
+    // point is in the torus frame, relative to the center
+    // u: [0,1] of angle around the axis, v: [0,1] of angle around the tube from the outside
+    fn hit_record(&self, ray: Ray, point: Vec3, t: f32) -> HitRecord {
+        let phi = point.y.atan2(point.x);
+        let theta = point.z.atan2(point.truncate().length() - self.major_radius);
+        let uv = Vec2::new(
+            phi.rem_euclid(2.0 * PI) / (2.0 * PI),
+            theta.rem_euclid(2.0 * PI) / (2.0 * PI),
+        );
+
+        let normal = Vec3::new(
+            theta.cos() * phi.cos(),
+            theta.cos() * phi.sin(),
+            theta.sin(),
+        );
+        let mut hit_record = HitRecord::new(
+            ray,
+            ray.at(t),
+            self.frame.transform(normal),
+            self.material.clone(),
+            t,
+            uv,
+        );
+        hit_record.dpdu = self
+            .frame
+            .transform(2.0 * PI * Vec3::new(-point.y, point.x, 0.0));
+        hit_record.dpdv = self.frame.transform(
+            2.0 * PI
+                * self.minor_radius
+                * Vec3::new(
+                    -theta.sin() * phi.cos(),
+                    -theta.sin() * phi.sin(),
+                    theta.cos(),
+                ),
+        );
+        hit_record
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let direction_length = ray.direction.length();
+        let origin = self.frame.to_local(ray.origin - self.center);
+        let direction = self.frame.to_local(ray.direction) / direction_length;
+
+        // solve from the point of the ray nearest the center, which keeps the coefficients
+        // well conditioned for distant rays
+        let offset = -origin.dot(direction);
+        let o = (origin + offset * direction).as_dvec3();
+        let d = direction.as_dvec3();
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) along p = o + s d, with |d| = 1
+        let major_squared = (self.major_radius as f64).powi(2);
+        let minor_squared = (self.minor_radius as f64).powi(2);
+        let f = o.dot(d);
+        let e = o.length_squared() + major_squared - minor_squared;
+        let coefficients = [
+            1.0,
+            4.0 * f,
+            4.0 * f * f + 2.0 * e - 4.0 * major_squared * (1.0 - d.z * d.z),
+            4.0 * f * e - 8.0 * major_squared * (f - o.z * d.z),
+            e * e - 4.0 * major_squared * (o.length_squared() - o.z * o.z),
+        ];
+
+        let s_range = (
+            (ray_t.min * direction_length - offset) as f64,
+            (ray_t.max * direction_length - offset) as f64,
+        );
+        let (roots, count) = quartic_roots(coefficients, s_range);
+
+        roots[..count]
+            .iter()
+            .map(|&s| (offset + s as f32) / direction_length)
+            .filter(|&t| ray_t.surrounds(t))
+            .map(|t| self.hit_record(ray, origin + t * direction_length * direction, t))
+            .find(|hit_record| self.material.alpha_test(hit_record, rng))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut dyn RngCore) -> f32 {
+        uniform_area_pdf_value(self, self.area(), origin, direction, rng)
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        // the outside of the tube has more area, by R + r cos(theta)
+        let theta = loop {
+            let theta = 2.0 * PI * rng.random::<f32>();
+            let acceptance = (self.major_radius + self.minor_radius * theta.cos())
+                / (self.major_radius + self.minor_radius);
+            if rng.random::<f32>() < acceptance {
+                break theta;
+            }
+        };
+        let phi = 2.0 * PI * rng.random::<f32>();
+
+        let ring = self.major_radius + self.minor_radius * theta.cos();
+        let point = Vec3::new(
+            ring * phi.cos(),
+            ring * phi.sin(),
+            self.minor_radius * theta.sin(),
+        );
+        self.center + self.frame.transform(point) - origin
+    }
+}
+
+// Polynomials have coefficients from the highest power down
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients
+        .iter()
+        .fold(0.0, |value, coefficient| value * x + coefficient)
+}
+
+fn derivative<const N: usize, const M: usize>(coefficients: [f64; N]) -> [f64; M] {
+    std::array::from_fn(|i| coefficients[i] * (N - 1 - i) as f64)
+}
+
+// Sorted real roots in range of a quartic, as an array and its used length
+// A polynomial is monotonic between the roots of its derivative, so each of those intervals
+// holds at most one root, found by bisection
+fn quartic_roots(coefficients: [f64; 5], range: (f64, f64)) -> ([f64; 4], usize) {
+    let cubic = derivative::<5, 4>(coefficients);
+    let quadratic = derivative::<4, 3>(cubic);
+
+    // no roots outside of the Cauchy bound
+    let bound = 1.0
+        + coefficients[1..]
+            .iter()
+            .map(|coefficient| (coefficient / coefficients[0]).abs())
+            .fold(0.0, f64::max);
+    let range = (range.0.max(-bound), range.1.min(bound));
+    if range.0 >= range.1 {
+        return ([0.0; 4], 0);
+    }
+
+    let (quadratic_roots, quadratic_count) = quadratic_roots(quadratic, range);
+    let (cubic_roots, cubic_count) =
+        bracketed_roots(&cubic, &quadratic_roots[..quadratic_count], range);
+    bracketed_roots(&coefficients, &cubic_roots[..cubic_count], range)
+}
+
+fn quadratic_roots(coefficients: [f64; 3], range: (f64, f64)) -> ([f64; 4], usize) {
+    let [a, b, c] = coefficients;
+    let mut roots = [0.0; 4];
+    let mut count = 0;
+
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return (roots, count);
+    }
+    // without cancellation between b and the root of the discriminant
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (mut x0, mut x1) = (q / a, c / q);
+    if x0 > x1 {
+        (x0, x1) = (x1, x0);
+    }
+    for x in [x0, x1] {
+        if x.is_finite() && x > range.0 && x < range.1 {
+            roots[count] = x;
+            count += 1;
+        }
+    }
+    (roots, count)
+}
+
+// Roots between consecutive critical points, in order
+fn bracketed_roots(
+    coefficients: &[f64],
+    critical_points: &[f64],
+    range: (f64, f64),
+) -> ([f64; 4], usize) {
+    let mut roots = [0.0; 4];
+    let mut count = 0;
+
+    let mut low = range.0;
+    for high in critical_points.iter().copied().chain([range.1]) {
+        let (mut a, mut b) = (low, high);
+        let (value_a, value_b) = (evaluate(coefficients, a), evaluate(coefficients, b));
+        low = high;
+        if value_a.signum() == value_b.signum() && value_a != 0.0 && value_b != 0.0 {
+            continue;
+        }
+
+        let rising = value_b > value_a;
+        for _ in 0..64 {
+            let middle = 0.5 * (a + b);
+            if middle <= a || middle >= b {
+                break;
+            }
+            if (evaluate(coefficients, middle) < 0.0) == rising {
+                a = middle;
+            } else {
+                b = middle;
+            }
+        }
+        if count < roots.len() {
+            roots[count] = 0.5 * (a + b);
+            count += 1;
+        }
+    }
+    (roots, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::LambertianMaterial, texture::SolidColor};
+
+    #[test]
+    fn quartic_roots_in_range() {
+        // (x + 3)(x - 1)(x - 2)(x - 5)
+        let coefficients = [1.0, -5.0, -7.0, 41.0, -30.0];
+
+        let (roots, count) = quartic_roots(coefficients, (-10.0, 10.0));
+        assert_eq!(count, 4);
+        for (root, expected) in roots.iter().zip([-3.0, 1.0, 2.0, 5.0]) {
+            assert!((root - expected).abs() < 1e-9, "{root} != {expected}");
+        }
+
+        let (roots, count) = quartic_roots(coefficients, (1.5, 4.0));
+        assert_eq!(count, 1);
+        assert!((roots[0] - 2.0).abs() < 1e-9);
+
+        assert_eq!(quartic_roots([1.0, 0.0, 0.0, 0.0, 1.0], (-10.0, 10.0)).1, 0);
+    }
+
+    #[test]
+    fn ray_hits() {
+        let material = Arc::new(LambertianMaterial::new(Arc::new(SolidColor::splat(0.5))));
+        let torus = Torus::new(Vec3::ZERO, Vec3::Z, 2.0, 0.5, material.clone());
+        let ray_t = Interval::new(0.001, f32::INFINITY);
+        let rng = &mut rand::rng();
+
+        let cases = [
+            // through the whole tube from outside, the first of four crossings
+            (Vec3::new(-5.0, 0.0, 0.0), Vec3::X, 2.5, Vec3::NEG_X),
+            // from the hole
+            (Vec3::ZERO, Vec3::X, 1.5, Vec3::NEG_X),
+            // onto the top of the tube, with a direction that is not unit length
+            (
+                Vec3::new(0.0, 2.0, 5.0),
+                Vec3::new(0.0, 0.0, -2.0),
+                2.25,
+                Vec3::Z,
+            ),
+        ];
+        for (origin, direction, t, normal) in cases {
+            let hit_record = torus.hit(Ray::new(origin, direction), ray_t, rng).unwrap();
+            assert!((hit_record.t - t).abs() < 1e-4, "{} != {t}", hit_record.t);
+            assert!(hit_record.normal.abs_diff_eq(normal, 1e-4));
+        }
+
+        assert!(
+            torus
+                .hit(Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z), ray_t, rng)
+                .is_none()
+        );
+        assert!(
+            torus
+                .hit(Ray::new(Vec3::new(-5.0, 0.0, 0.6), Vec3::X), ray_t, rng)
+                .is_none()
+        );
+
+        // tilted so the ring lies in the yz plane
+        let tilted = Torus::new(Vec3::new(1.0, 0.0, 0.0), Vec3::X, 2.0, 0.5, material);
+        let hit_record = tilted
+            .hit(Ray::new(Vec3::new(6.0, 0.0, 2.0), Vec3::NEG_X), ray_t, rng)
+            .unwrap();
+        assert!((hit_record.t - 4.5).abs() < 1e-4);
+        assert!(hit_record.point.abs_diff_eq(Vec3::new(1.5, 0.0, 2.0), 1e-4));
+    }
+}
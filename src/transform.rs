@@ -1,13 +1,13 @@
 use std::sync::Arc;
 
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Quat, Vec3};
 use rand::RngCore;
 
 use crate::{
     aabb::Aabb,
     hit::{HitRecord, Hittable},
     interval::Interval,
-    ray::Ray,
+    ray::{Ray, RayDifferential},
 };
 
 #[derive(Debug)]
@@ -24,59 +24,262 @@ impl Transform {
         let transform_inv = transform.inverse();
         let transform_inv_t = transform_inv.transpose();
 
-        let mut bbox = object.bounding_box();
+        let bbox = transformed_bbox(object.bounding_box(), transform);
 
-        let mut min = Vec3::INFINITY;
-        let mut max = Vec3::NEG_INFINITY;
+        Self {
+            object,
+            transform: *transform,
+            transform_inv,
+            transform_inv_t,
+            bbox,
+        }
+    }
+}
 
-        // Transform bounding box corners
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let corner = Vec3::new(
-                        if i == 1 { bbox.x.max } else { bbox.x.min },
-                        if j == 1 { bbox.y.max } else { bbox.y.min },
-                        if k == 1 { bbox.z.max } else { bbox.z.min },
-                    );
+impl Hittable for Transform {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let ray_transformed = transform_ray(&self.transform_inv, ray);
+        let hit_record = self.object.hit(ray_transformed, ray_t, rng)?;
+        Some(transform_hit_record(
+            &self.transform,
+            &self.transform_inv_t,
+            hit_record,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
 
-                    let transformed = transform.transform_point3(corner);
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3, _rng: &mut dyn RngCore) -> f32 {
+        0.0
+    }
 
-                    min = min.min(transformed);
-                    max = max.max(transformed);
+    fn random(&self, _origin: Vec3, _rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::X
+    }
+}
+
+fn bbox_corners(bbox: Aabb) -> [Vec3; 8] {
+    std::array::from_fn(|i| {
+        Vec3::new(
+            if i & 1 == 1 { bbox.x.max } else { bbox.x.min },
+            if i & 2 == 2 { bbox.y.max } else { bbox.y.min },
+            if i & 4 == 4 { bbox.z.max } else { bbox.z.min },
+        )
+    })
+}
+
+fn transformed_bbox(bbox: Aabb, transform: &Mat4) -> Aabb {
+    // infinite corners would turn into NaN, so unbounded boxes go axis by axis instead,
+    // leaving out the axes that don't contribute
+    if !bbox.is_bounded() {
+        let mut transformed = Aabb::EMPTY;
+        for axis in 0..3 {
+            let translation = transform.w_axis[axis];
+            let mut interval = Interval::new(translation, translation);
+            for column in 0..3 {
+                let scale = transform.col(column)[axis];
+                if scale == 0.0 {
+                    continue;
                 }
+                let (a, b) = (scale * bbox[column].min, scale * bbox[column].max);
+                interval.min += a.min(b);
+                interval.max += a.max(b);
             }
+            transformed[axis] = interval;
         }
+        return transformed.padded_to_mins();
+    }
+
+    let mut min = Vec3::INFINITY;
+    let mut max = Vec3::NEG_INFINITY;
+
+    // Transform bounding box corners
+    for corner in bbox_corners(bbox) {
+        let transformed = transform.transform_point3(corner);
+
+        min = min.min(transformed);
+        max = max.max(transformed);
+    }
 
-        bbox = Aabb::from_corners(min, max);
+    Aabb::from_corners(min, max)
+}
+
+// Into object space, keeping the time and the differentials
+fn transform_ray(transform_inv: &Mat4, ray: Ray) -> Ray {
+    let differential = ray.differential.map(|differential| RayDifferential {
+        rx_origin: transform_inv.transform_point3(differential.rx_origin),
+        rx_direction: transform_inv.transform_vector3(differential.rx_direction),
+        ry_origin: transform_inv.transform_point3(differential.ry_origin),
+        ry_direction: transform_inv.transform_vector3(differential.ry_direction),
+    });
+    Ray::with_differential(
+        transform_inv.transform_point3(ray.origin),
+        transform_inv.transform_vector3(ray.direction),
+        ray.time,
+        differential,
+    )
+}
+
+// Back into world space
+fn transform_hit_record(
+    transform: &Mat4,
+    transform_inv_t: &Mat4,
+    mut hit_record: HitRecord,
+) -> HitRecord {
+    hit_record.point = transform.transform_point3(hit_record.point);
+    hit_record.normal = transform_inv_t
+        .transform_vector3(hit_record.normal)
+        .normalize();
+    hit_record.dpdu = transform.transform_vector3(hit_record.dpdu);
+    hit_record.dpdv = transform.transform_vector3(hit_record.dpdv);
+    hit_record.geometric_normal = transform_inv_t
+        .transform_vector3(hit_record.geometric_normal)
+        .normalize();
+    hit_record
+}
 
+// Translation, rotation and scale of an AnimatedTransform at time
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TransformKeyframe {
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl TransformKeyframe {
+    pub const fn new(time: f32, translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
         Self {
-            object,
-            transform: *transform,
-            transform_inv,
-            transform_inv_t,
-            bbox,
+            time,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    // Shear in the matrix is lost
+    pub fn from_matrix(time: f32, transform: &Mat4) -> Self {
+        let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+        Self::new(time, translation, rotation, scale)
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    // Translation and scale are interpolated linearly and rotation along the shortest arc
+    pub fn interpolated(&self, other: &Self, t: f32) -> Self {
+        Self {
+            time: self.time + t * (other.time - self.time),
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
         }
     }
 }
 
-impl Hittable for Transform {
-    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
-        let ray_transformed = Ray::new(
-            self.transform_inv.transform_point3(ray.origin),
-            self.transform_inv.transform_vector3(ray.direction),
+// Object moving through keyframes over the ray times, held at the first and last keyframes
+// outside of them
+#[derive(Debug)]
+pub struct AnimatedTransform {
+    object: Arc<dyn Hittable>,
+    keyframes: Vec<TransformKeyframe>, // sorted by time
+    bbox: Aabb,
+}
+
+impl AnimatedTransform {
+    // Bounds are taken at this many steps between two keyframes, widened for the rotation
+    // between steps
+    const BBOX_STEPS: usize = 16;
+
+    pub fn new(object: Arc<dyn Hittable>, mut keyframes: Vec<TransformKeyframe>) -> Self {
+        assert!(
+            !keyframes.is_empty(),
+            "an animated transform needs at least one keyframe"
         );
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let object_bbox = object.bounding_box();
+        let mut bbox = transformed_bbox(object_bbox, &keyframes[0].matrix());
+        for pair in keyframes.windows(2) {
+            // a corner rotating by angle strays at most radius * (1 - cos(angle / 2)) from the
+            // chord between two steps
+            let step_angle =
+                pair[0].rotation.angle_between(pair[1].rotation) / Self::BBOX_STEPS as f32;
+            let radius = bbox_corners(object_bbox)
+                .iter()
+                .map(|corner| corner.length())
+                .fold(0.0, f32::max)
+                * pair[0].scale.abs().max(pair[1].scale.abs()).max_element();
+            let margin = radius * (1.0 - (0.5 * step_angle).cos());
+
+            for step in 1..=Self::BBOX_STEPS {
+                let keyframe =
+                    pair[0].interpolated(&pair[1], step as f32 / Self::BBOX_STEPS as f32);
+                let (min, max) = transformed_bbox(object_bbox, &keyframe.matrix()).get_corners();
+                bbox.merge(Aabb::from_corners(
+                    min - Vec3::splat(margin),
+                    max + Vec3::splat(margin),
+                ));
+            }
+        }
+
+        Self {
+            object,
+            keyframes,
+            bbox,
+        }
+    }
+
+    // Straight from start to end over the default shutter interval
+    pub fn between(object: Arc<dyn Hittable>, start: &Mat4, end: &Mat4) -> Self {
+        Self::new(
+            object,
+            vec![
+                TransformKeyframe::from_matrix(0.0, start),
+                TransformKeyframe::from_matrix(1.0, end),
+            ],
+        )
+    }
+
+    pub fn keyframes(&self) -> &[TransformKeyframe] {
+        &self.keyframes
+    }
 
-        let Some(mut hit_record) = self.object.hit(ray_transformed, ray_t, rng) else {
-            return None;
+    pub fn keyframe_at(&self, time: f32) -> TransformKeyframe {
+        let next = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        let previous = &self.keyframes[next - 1];
+        let Some(next) = self.keyframes.get(next) else {
+            return *previous;
         };
+        previous.interpolated(next, (time - previous.time) / (next.time - previous.time))
+    }
 
-        hit_record.point = self.transform.transform_point3(hit_record.point);
-        hit_record.normal = self
-            .transform_inv_t
-            .transform_vector3(hit_record.normal)
-            .normalize();
+    pub fn matrix_at(&self, time: f32) -> Mat4 {
+        self.keyframe_at(time).matrix()
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let transform = self.matrix_at(ray.time);
+        let transform_inv = transform.inverse();
 
-        Some(hit_record)
+        let hit_record = self
+            .object
+            .hit(transform_ray(&transform_inv, ray), ray_t, rng)?;
+        Some(transform_hit_record(
+            &transform,
+            &transform_inv.transpose(),
+            hit_record,
+        ))
     }
 
     fn bounding_box(&self) -> Aabb {
@@ -16,10 +16,13 @@ pub struct Triangle {
     a: Vec3,
     ab: Vec3, // replace with b and c?
     ac: Vec3,
-    uvs: [Vec2; 3], // a, b, c
+    uvs: [Vec2; 3],                    // a, b, c
+    vertex_normals: Option<[Vec3; 3]>, // a, b, c
     material: Arc<dyn Material>,
     bbox: Aabb,
     normal: Vec3,
+    dpdu: Vec3,
+    dpdv: Vec3,
 }
 
 impl Triangle {
@@ -31,21 +34,64 @@ impl Triangle {
         let n = ab.cross(ac);
         let normal = n.normalize();
 
+        let (dpdu, dpdv) = uv_derivatives(ab, ac, uvs);
+
         Self {
             a,
             ab,
             ac,
             uvs,
+            vertex_normals: None,
             material,
             bbox,
             normal,
+            dpdu,
+            dpdv,
+        }
+    }
+
+    // Smooth shaded triangle, normals are interpolated at the hit
+    // The geometric normal is flipped to agree with the vertex normals if the winding doesn't
+    pub fn with_normals(
+        a: Vec3,
+        ab: Vec3,
+        ac: Vec3,
+        uvs: [Vec2; 3],
+        vertex_normals: [Vec3; 3],
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let mut triangle = Self::new(a, ab, ac, uvs, material);
+
+        let normal_sum = vertex_normals[0] + vertex_normals[1] + vertex_normals[2];
+        if triangle.normal.dot(normal_sum) < 0.0 {
+            triangle.normal = -triangle.normal;
         }
+        triangle.vertex_normals = Some(vertex_normals.map(|normal| normal.normalize_or_zero()));
+
+        triangle
     }
 }
 
+// Solves ab = dpdu * duv_ab.x + dpdv * duv_ab.y (and the same for ac) for the surface derivatives
+// Returns zeros when the texture coordinates are degenerate
+pub fn uv_derivatives(ab: Vec3, ac: Vec3, uvs: [Vec2; 3]) -> (Vec3, Vec3) {
+    let duv_ab = uvs[1] - uvs[0];
+    let duv_ac = uvs[2] - uvs[0];
+    let det = duv_ab.x * duv_ac.y - duv_ab.y * duv_ac.x;
+    if det.abs() < 1e-12 {
+        return (Vec3::ZERO, Vec3::ZERO);
+    }
+
+    let inv_det = 1.0 / det;
+    (
+        (duv_ac.y * ab - duv_ab.y * ac) * inv_det,
+        (duv_ab.x * ac - duv_ac.x * ab) * inv_det,
+    )
+}
+
 impl Hittable for Triangle {
     // moller trumbore from scratchapixel
-    fn hit(&self, ray: Ray, ray_t: Interval, _rng: &mut dyn RngCore) -> Option<HitRecord> {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
         let pvec = ray.direction.cross(self.ac);
         let det = self.ab.dot(pvec);
 
@@ -80,14 +126,23 @@ impl Hittable for Triangle {
 
         let uv = (1.0 - u - v) * self.uvs[0] + u * self.uvs[1] + v * self.uvs[2];
 
-        Some(HitRecord {
-            point: hit_point,
-            normal: self.normal,
-            material: self.material.clone(),
-            t,
-            uv,
-            front_face: det >= 0.0,
-        })
+        let mut hit_record =
+            HitRecord::new(ray, hit_point, self.normal, self.material.clone(), t, uv);
+        hit_record.dpdu = self.dpdu;
+        hit_record.dpdv = self.dpdv;
+
+        if let Some(normals) = self.vertex_normals {
+            let shading_normal = (1.0 - u - v) * normals[0] + u * normals[1] + v * normals[2];
+            if let Some(shading_normal) = shading_normal.try_normalize() {
+                hit_record.set_shading_normal(shading_normal);
+            }
+        }
+
+        if !self.material.alpha_test(&hit_record, rng) {
+            return None;
+        }
+
+        Some(hit_record)
     }
 
     fn bounding_box(&self) -> Aabb {
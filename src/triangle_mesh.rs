@@ -0,0 +1,462 @@
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3, Vec4};
+use rand::RngCore;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    triangle::uv_derivatives,
+};
+
+// Faces per BVH leaf
+const MAX_LEAF_FACES: usize = 4;
+
+// Triangles sharing vertex buffers, with an internal BVH over the faces
+// Costs a few dozen bytes per face against several hundred for a BVH of Triangles, and hits
+// don't go through dynamic dispatch until the material
+#[derive(Debug)]
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>, // per vertex, like the optional buffers below
+    uvs: Option<Vec<Vec2>>,
+    tangents: Option<Vec<Vec4>>,
+    colors: Option<Vec<Vec3>>, // linear
+    faces: Vec<[u32; 3]>,      // in BVH order
+    materials: Vec<Arc<dyn Material>>,
+    material_indices: Vec<u32>, // per face, empty when there's a single material
+    nodes: Vec<MeshBvhNode>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MeshBvhNode {
+    bbox: Aabb,
+    // leaves hold face_count faces from first, interior nodes have their left child right after
+    // them and the right child at first
+    first: u32,
+    face_count: u32,
+    axis: u8,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3>, faces: Vec<[u32; 3]>, material: Arc<dyn Material>) -> Self {
+        Self::with_materials(positions, faces, vec![material], Vec::new())
+    }
+
+    // material_indices picks one of materials for each face
+    pub fn with_materials(
+        positions: Vec<Vec3>,
+        faces: Vec<[u32; 3]>,
+        materials: Vec<Arc<dyn Material>>,
+        material_indices: Vec<u32>,
+    ) -> Self {
+        assert!(!materials.is_empty(), "a mesh needs at least one material");
+        assert!(
+            material_indices.is_empty() || material_indices.len() == faces.len(),
+            "one material index per face"
+        );
+        assert!(
+            faces
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < positions.len()),
+            "face index out of range"
+        );
+        assert!(
+            material_indices
+                .iter()
+                .all(|&i| (i as usize) < materials.len()),
+            "material index out of range"
+        );
+
+        let mut mesh = Self {
+            positions,
+            normals: None,
+            uvs: None,
+            tangents: None,
+            colors: None,
+            faces,
+            materials,
+            material_indices,
+            nodes: Vec::new(),
+        };
+        mesh.build_bvh();
+        mesh
+    }
+
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(normals.len(), self.positions.len(), "one normal per vertex");
+        self.normals = Some(
+            normals
+                .into_iter()
+                .map(|normal| normal.normalize_or_zero())
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<Vec2>) -> Self {
+        assert_eq!(uvs.len(), self.positions.len(), "one uv per vertex");
+        self.uvs = Some(uvs);
+        self
+    }
+
+    // Orients dpdu and dpdv along an authored tangent frame, averaged over each face's vertices
+    // Tangents are glTF style, xyz along +u and w the handedness of the bitangent
+    pub fn with_tangents(mut self, tangents: Vec<Vec4>) -> Self {
+        assert_eq!(
+            tangents.len(),
+            self.positions.len(),
+            "one tangent per vertex"
+        );
+        self.tangents = Some(tangents);
+        self
+    }
+
+    // Read through VertexColorTexture
+    pub fn with_colors(mut self, colors: Vec<Vec3>) -> Self {
+        assert_eq!(colors.len(), self.positions.len(), "one color per vertex");
+        self.colors = Some(colors);
+        self
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn face_bbox(&self, face: [u32; 3]) -> Aabb {
+        let [a, b, c] = face.map(|i| self.positions[i as usize]);
+        Aabb::from_corners(a.min(b).min(c), a.max(b).max(c))
+    }
+
+    // Median splits on the longest axis of the centroids, like BvhNode, into a flat node array
+    // Faces and their material indices are reordered so every leaf is a contiguous range
+    fn build_bvh(&mut self) {
+        let centroids: Vec<Vec3> = self
+            .faces
+            .iter()
+            .map(|face| {
+                face.map(|i| self.positions[i as usize])
+                    .iter()
+                    .sum::<Vec3>()
+                    / 3.0
+            })
+            .collect();
+        let mut order: Vec<u32> = (0..self.faces.len() as u32).collect();
+
+        self.nodes = Vec::with_capacity(2 * self.faces.len() / MAX_LEAF_FACES + 1);
+        if !order.is_empty() {
+            self.build_node(&mut order, 0, &centroids);
+        }
+
+        self.faces = order.iter().map(|&i| self.faces[i as usize]).collect();
+        if !self.material_indices.is_empty() {
+            self.material_indices = order
+                .iter()
+                .map(|&i| self.material_indices[i as usize])
+                .collect();
+        }
+    }
+
+    fn build_node(&mut self, order: &mut [u32], first: usize, centroids: &[Vec3]) {
+        let mut bbox = Aabb::EMPTY;
+        let mut centroid_min = Vec3::MAX;
+        let mut centroid_max = Vec3::MIN;
+        for &face in order.iter() {
+            bbox.merge(self.face_bbox(self.faces[face as usize]));
+            centroid_min = centroid_min.min(centroids[face as usize]);
+            centroid_max = centroid_max.max(centroids[face as usize]);
+        }
+
+        let node_index = self.nodes.len();
+        self.nodes.push(MeshBvhNode {
+            bbox,
+            first: first as u32,
+            face_count: order.len() as u32,
+            axis: 0,
+        });
+        if order.len() <= MAX_LEAF_FACES {
+            return;
+        }
+
+        let axis = Aabb::from_corners(centroid_min, centroid_max).longest_axis();
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by(mid, |&a, &b| {
+            centroids[a as usize][axis].total_cmp(&centroids[b as usize][axis])
+        });
+
+        let (left, right) = order.split_at_mut(mid);
+        self.build_node(left, first, centroids);
+        let right_index = self.nodes.len();
+        self.build_node(right, first + mid, centroids);
+
+        let node = &mut self.nodes[node_index];
+        node.first = right_index as u32;
+        node.face_count = 0;
+        node.axis = axis as u8;
+    }
+
+    // moller trumbore, returns t and the barycentric coordinates of b and c
+    fn intersect_face(&self, face: [u32; 3], ray: Ray, ray_t: Interval) -> Option<(f32, f32, f32)> {
+        let a = self.positions[face[0] as usize];
+        let ab = self.positions[face[1] as usize] - a;
+        let ac = self.positions[face[2] as usize] - a;
+
+        let pvec = ray.direction.cross(ac);
+        let det = ab.dot(pvec);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+
+        let tvec = ray.origin - a;
+        let u = tvec.dot(pvec) / det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let qvec = tvec.cross(ab);
+        let v = ray.direction.dot(qvec) / det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = ac.dot(qvec) / det;
+        ray_t.surrounds(t).then_some((t, u, v))
+    }
+
+    fn hit_record(&self, face_index: usize, ray: Ray, t: f32, u: f32, v: f32) -> HitRecord {
+        let face = self.faces[face_index].map(|i| i as usize);
+        let weights = [1.0 - u - v, u, v];
+        let interpolate3 =
+            |values: &[Vec3]| (0..3).map(|k| weights[k] * values[face[k]]).sum::<Vec3>();
+
+        let a = self.positions[face[0]];
+        let ab = self.positions[face[1]] - a;
+        let ac = self.positions[face[2]] - a;
+        let mut normal = ab.cross(ac).normalize();
+
+        let uvs = match &self.uvs {
+            Some(uvs) => face.map(|i| uvs[i]),
+            None => [Vec2::ZERO, Vec2::X, Vec2::Y],
+        };
+        let uv = weights[0] * uvs[0] + weights[1] * uvs[1] + weights[2] * uvs[2];
+
+        let material = match self.material_indices.get(face_index) {
+            Some(&index) => self.materials[index as usize].clone(),
+            None => self.materials[0].clone(),
+        };
+
+        // the geometric normal agrees with the vertex normals whatever the winding, as for
+        // Triangle::with_normals
+        if let Some(normals) = &self.normals
+            && normal.dot(normals[face[0]] + normals[face[1]] + normals[face[2]]) < 0.0
+        {
+            normal = -normal;
+        }
+
+        let mut hit_record = HitRecord::new(ray, ray.at(t), normal, material, t, uv);
+        (hit_record.dpdu, hit_record.dpdv) = uv_derivatives(ab, ac, uvs);
+
+        if let Some(tangents) = &self.tangents {
+            let face_normal = match &self.normals {
+                Some(normals) => {
+                    (normals[face[0]] + normals[face[1]] + normals[face[2]]).normalize_or(normal)
+                }
+                None => normal,
+            };
+            (hit_record.dpdu, hit_record.dpdv) = tangent_derivatives(
+                face_normal,
+                face.map(|i| tangents[i]),
+                hit_record.dpdu,
+                hit_record.dpdv,
+            );
+        }
+
+        if let Some(normals) = &self.normals
+            && let Some(shading_normal) = interpolate3(normals).try_normalize()
+        {
+            hit_record.set_shading_normal(shading_normal);
+        }
+        if let Some(colors) = &self.colors {
+            hit_record.vertex_color = Some(interpolate3(colors));
+        }
+
+        hit_record
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: Ray, ray_t: Interval, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest: Option<HitRecord> = None;
+        let mut closest_t = ray_t.max;
+
+        // median splits keep the tree depth near log2 of the face count
+        let mut stack = [0u32; 64];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node_index = stack[stack_len];
+            let node = self.nodes[node_index as usize];
+            if !node.bbox.hit(ray, Interval::new(ray_t.min, closest_t)) {
+                continue;
+            }
+
+            if node.face_count == 0 {
+                // push the far child first so the near one is visited first and shrinks closest_t
+                let left = node_index + 1;
+                let right = node.first;
+                let (near, far) = if ray.direction[node.axis as usize] < 0.0 {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                stack[stack_len] = far;
+                stack[stack_len + 1] = near;
+                stack_len += 2;
+                continue;
+            }
+
+            let first = node.first as usize;
+            for face_index in first..first + node.face_count as usize {
+                let Some((t, u, v)) = self.intersect_face(
+                    self.faces[face_index],
+                    ray,
+                    Interval::new(ray_t.min, closest_t),
+                ) else {
+                    continue;
+                };
+                let hit_record = self.hit_record(face_index, ray, t, u, v);
+                if hit_record.material.alpha_test(&hit_record, rng) {
+                    closest_t = t;
+                    closest = Some(hit_record);
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |root| root.bbox)
+    }
+
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3, _rng: &mut dyn RngCore) -> f32 {
+        0.0
+    }
+
+    fn random(&self, _origin: Vec3, _rng: &mut dyn RngCore) -> Vec3 {
+        Vec3::X
+    }
+}
+
+// dpdu and dpdv turned to the tangent frame averaged over the face, for the face normal
+// The lengths still come from the texture coordinates so bump mapping keeps its scale
+fn tangent_derivatives(normal: Vec3, tangents: [Vec4; 3], dpdu: Vec3, dpdv: Vec3) -> (Vec3, Vec3) {
+    let tangent: Vec3 = tangents.iter().map(|tangent| tangent.truncate()).sum();
+    let Some(tangent) = (tangent - normal * normal.dot(tangent)).try_normalize() else {
+        return (dpdu, dpdv);
+    };
+    let handedness = if tangents[0].w < 0.0 { -1.0 } else { 1.0 };
+    let bitangent = handedness * normal.cross(tangent);
+
+    let length_or_one = |v: Vec3| if v == Vec3::ZERO { 1.0 } else { v.length() };
+    (
+        length_or_one(dpdu) * tangent,
+        length_or_one(dpdv) * bitangent,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::LambertianMaterial, texture::SolidColor};
+
+    fn material(gray: f32) -> Arc<dyn Material> {
+        Arc::new(LambertianMaterial::new(Arc::new(SolidColor::splat(gray))))
+    }
+
+    // Two n x n grids of unit quads facing +z, at z = 0 and z = -1, with a material each
+    fn layered_grids(n: u32) -> (TriangleMesh, [Arc<dyn Material>; 2]) {
+        let mut positions = Vec::new();
+        let mut faces = Vec::new();
+        let mut material_indices = Vec::new();
+        for layer in 0..2 {
+            let first = positions.len() as u32;
+            for y in 0..=n {
+                for x in 0..=n {
+                    positions.push(Vec3::new(x as f32, y as f32, -(layer as f32)));
+                }
+            }
+            for y in 0..n {
+                for x in 0..n {
+                    let corner = first + y * (n + 1) + x;
+                    faces.push([corner, corner + 1, corner + n + 2]);
+                    faces.push([corner, corner + n + 2, corner + n + 1]);
+                    material_indices.extend([layer, layer]);
+                }
+            }
+        }
+        let materials = [material(0.25), material(0.75)];
+        let mesh =
+            TriangleMesh::with_materials(positions, faces, materials.to_vec(), material_indices);
+        (mesh, materials)
+    }
+
+    fn hit(mesh: &TriangleMesh, origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        mesh.hit(
+            Ray::new(origin, direction),
+            Interval::new(0.001, f32::INFINITY),
+            &mut rand::rng(),
+        )
+    }
+
+    #[test]
+    fn closest_face_through_the_bvh() {
+        let (mesh, materials) = layered_grids(8);
+        assert_eq!(mesh.face_count(), 256);
+        assert_eq!(mesh.bounding_box().x.max, 8.0);
+
+        for (x, y) in [(0.25, 0.75), (3.6, 5.1), (7.9, 0.1)] {
+            let front = hit(&mesh, Vec3::new(x, y, 2.0), Vec3::NEG_Z).unwrap();
+            assert!((front.t - 2.0).abs() < 1e-5);
+            assert_eq!(front.point.truncate(), Vec2::new(x, y));
+            assert!(Arc::ptr_eq(&front.material, &materials[0]));
+
+            let back = hit(&mesh, Vec3::new(x, y, -3.0), Vec3::Z).unwrap();
+            assert!((back.t - 2.0).abs() < 1e-5);
+            assert!(Arc::ptr_eq(&back.material, &materials[1]));
+            assert_eq!(back.normal, Vec3::NEG_Z);
+        }
+    }
+
+    #[test]
+    fn oblique_ray_and_misses() {
+        let (mesh, _) = layered_grids(8);
+
+        // from (1, 1, 4) towards (5, 3, 0), crossing many leaves before the hit
+        let direction = Vec3::new(4.0, 2.0, -4.0);
+        let hit_record = hit(&mesh, Vec3::new(1.0, 1.0, 4.0), direction).unwrap();
+        assert!((hit_record.t - 1.0).abs() < 1e-5);
+
+        assert!(hit(&mesh, Vec3::new(9.0, 4.0, 2.0), Vec3::NEG_Z).is_none());
+        assert!(hit(&mesh, Vec3::new(4.0, 4.0, 2.0), Vec3::Z).is_none());
+        assert!(hit(&mesh, Vec3::new(4.0, 4.0, 2.0), Vec3::X).is_none());
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let mesh = TriangleMesh::new(Vec::new(), Vec::new(), material(0.5));
+        assert!(hit(&mesh, Vec3::ZERO, Vec3::X).is_none());
+    }
+}